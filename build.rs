@@ -0,0 +1,59 @@
+//! Generates the list of files embedded as the default asset pack, and a constant for the id of every
+//! asset, from `assets/manifest.ron`.
+
+use std::{collections::HashMap, env, fmt::Write, fs, path::Path};
+
+use nanoserde::DeRon;
+
+#[allow(dead_code)]
+#[path = "src/manifest.rs"]
+mod manifest;
+
+fn main() {
+    println!("cargo:rerun-if-changed=assets/manifest.ron");
+    println!("cargo:rerun-if-changed=src/manifest.rs");
+
+    let assets = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("assets");
+    let source = fs::read_to_string(assets.join("manifest.ron")).unwrap();
+    let manifest: manifest::Manifest = DeRon::deserialize_ron(&source)
+        .unwrap_or_else(|e| panic!("assets/manifest.ron is invalid: {e}"));
+    manifest
+        .check(&manifest, |path| assets.join(path).is_file())
+        .unwrap_or_else(|e| panic!("assets/manifest.ron is invalid: {e}"));
+
+    let mut files = String::from("&[\n");
+    for path in manifest.files() {
+        let full = assets.join(path);
+        println!("cargo:rerun-if-changed={}", full.display());
+        writeln!(
+            files,
+            "    ({path:?}, include_bytes!({:?})),",
+            full.display().to_string()
+        )
+        .unwrap();
+    }
+    files.push(']');
+
+    // every id gets a constant, so ids shared between kinds only get one
+    let mut constants: HashMap<String, &str> = HashMap::new();
+    let mut ids = String::new();
+    for (_, kind_ids) in manifest.ids() {
+        for id in kind_ids {
+            let name = id.to_uppercase();
+            match constants.get(&name) {
+                Some(&other) if other != id => {
+                    panic!("asset ids {other} and {id} both become the constant {name}")
+                }
+                Some(_) => {}
+                None => {
+                    constants.insert(name.clone(), id);
+                    writeln!(ids, "pub const {name}: &str = {id:?};").unwrap();
+                }
+            }
+        }
+    }
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).to_path_buf();
+    fs::write(out.join("embedded_files.rs"), files).unwrap();
+    fs::write(out.join("asset_ids.rs"), ids).unwrap();
+}
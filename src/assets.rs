@@ -1,135 +1,304 @@
-use std::collections::HashMap;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use std::borrow::Cow;
+use std::{
+    cell::{Cell, Ref, RefCell},
+    collections::HashMap,
+};
 
 use asefile::AsepriteFile;
 use image::EncodableLayout;
 use macroquad::prelude::*;
 
-pub struct Assets {
-    pub player_torso: AnimationsGroup,
-    pub player_legs: AnimationsGroup,
-    pub player_die: Texture2D,
-
-    pub terrain_tileset: Spritesheet,
-    pub obstacles_tileset: Spritesheet,
-    pub character_tileset: Spritesheet,
-
-    pub enemies: AnimationsGroup,
-
-    pub font: Font,
-
-    // ui
-    pub logo: Texture2D,
-    pub menu_play_btn: Animation,
-    pub menu_create_btn: Animation,
+use crate::{autotile::Autotile, manifest::Roles, pack::AssetPack};
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::{
+    hot_reload::{AssetWatcher, assets_dir},
+    manifest::MANIFEST_PATH,
+};
 
-    pub create_btn: Animation,
-
-    pub pause_btn: Animation,
-    pub pause_btn_topbar: Animation,
-
-    pub person_icon: Texture2D,
-    pub time_icon: Texture2D,
-    pub download_icon: Texture2D,
-
-    pub upload_btn: Animation,
-    pub rename_btn: Animation,
-    pub delete_btn: Animation,
+/// Id of every asset of the default pack, generated from its manifest by the build script
+#[allow(dead_code, reason = "some assets are only used through roles")]
+pub mod ids {
+    include!(concat!(env!("OUT_DIR"), "/asset_ids.rs"));
+}
 
-    pub tile_btn: Animation,
-    pub obstacles_btn: Animation,
-    pub character_btn: Animation,
-    pub handle_btn: Animation,
-    pub play_btn: Animation,
-    pub tool_btns: [Animation; 3],
-    pub spinner: Animation,
-    pub warning: Texture2D,
-    pub check: Texture2D,
+/// Loaded assets of one kind, by id.
+///
+/// Assets are leaked so references to them can be handed out while the registry itself can still change
+/// when assets are hot reloaded. Replaced assets stay alive, which is fine for debug builds.
+type Registry<T> = RefCell<HashMap<String, &'static T>>;
 
-    pub reload_btn: Animation,
-    pub sort_time_btn: Animation,
-    pub sort_downloads_btn: Animation,
-    pub sort_name_btn: Animation,
+/// Every loaded asset, in registries keyed by the ids from the [Manifest](crate::manifest::Manifest).
+pub struct Assets {
+    fonts: Registry<Font>,
+    textures: Registry<Texture2D>,
+    animations: Registry<Animation>,
+    animation_groups: Registry<AnimationsGroup>,
+    tilesets: Registry<Spritesheet>,
+    roles: RefCell<Roles>,
+    pack: RefCell<AssetPack>,
+    /// Increased every time assets are reloaded, so things built from them know to rebuild
+    generation: Cell<u32>,
+    /// Watches the files of the pack, if it was loaded from disk
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    watcher: Option<RefCell<AssetWatcher>>,
 }
 impl Default for Assets {
+    /// Debug builds load the assets from disk so they can be hot reloaded,
+    /// otherwise (or if that fails) the embedded default pack is used.
     fn default() -> Self {
-        let mut font = load_ttf_font_from_bytes(include_bytes!("../assets/pix32.ttf")).unwrap();
-        font.set_filter(FilterMode::Nearest);
-        Self {
-            font,
-
-            enemies: AnimationsGroup::from_file(include_bytes!("../assets/enemies.ase")),
-
-            player_torso: AnimationsGroup::from_file(include_bytes!("../assets/player_torso.ase")),
-            player_legs: AnimationsGroup::from_file(include_bytes!("../assets/player_legs.ase")),
-            player_die: load_ase_texture(include_bytes!("../assets/player_die.ase"), None),
-
-            terrain_tileset: Spritesheet::new(
-                load_ase_texture(include_bytes!("../assets/terrain_tileset.ase"), None),
-                16.0,
-            )
-            .autotile(
-                load_ase_texture(include_bytes!("../assets/terrain.ase"), Some(0)),
-                load_ase_texture(include_bytes!("../assets/terrain.ase"), Some(1)),
-            ),
-            obstacles_tileset: Spritesheet::new(
-                load_ase_texture(include_bytes!("../assets/obstacles_tileset.ase"), Some(1)),
-                16.0,
-            ),
-            character_tileset: Spritesheet::new(
-                load_ase_texture(include_bytes!("../assets/character_tileset.ase"), Some(1)),
-                16.0,
-            ),
-
-            logo: load_ase_texture(include_bytes!("../assets/ui/logo.ase"), None),
-            menu_play_btn: Animation::from_file(include_bytes!("../assets/ui/menu_play_btn.ase")),
-            menu_create_btn: Animation::from_file(include_bytes!(
-                "../assets/ui/menu_create_btn.ase"
-            )),
-            create_btn: Animation::from_file(include_bytes!("../assets/ui/create_btn.ase")),
-
-            pause_btn: Animation::from_file(include_bytes!("../assets/ui/pause_btn.ase")),
-            pause_btn_topbar: Animation::from_file(include_bytes!(
-                "../assets/ui/pause_btn_topbar.ase"
-            )),
-
-            person_icon: load_ase_texture(include_bytes!("../assets/ui/person_icon.ase"), Some(0)),
-            time_icon: load_ase_texture(include_bytes!("../assets/ui/time_icon.ase"), Some(0)),
-            download_icon: load_ase_texture(
-                include_bytes!("../assets/ui/download_icon.ase"),
-                Some(0),
-            ),
-
-            upload_btn: Animation::from_file(include_bytes!("../assets/ui/upload_btn.ase")),
-            rename_btn: Animation::from_file(include_bytes!("../assets/ui/rename_btn.ase")),
-            delete_btn: Animation::from_file(include_bytes!("../assets/ui/delete_btn.ase")),
-
-            tile_btn: Animation::from_file(include_bytes!("../assets/ui/tile_btn.ase")),
-            obstacles_btn: Animation::from_file(include_bytes!("../assets/ui/obstacles_btn.ase")),
-            character_btn: Animation::from_file(include_bytes!("../assets/ui/character_btn.ase")),
-            handle_btn: Animation::from_file(include_bytes!("../assets/ui/handle_btn.ase")),
-            play_btn: Animation::from_file(include_bytes!("../assets/ui/play_btn.ase")),
-            spinner: Animation::from_file(include_bytes!("../assets/ui/spinner.ase")),
-            warning: load_ase_texture(include_bytes!("../assets/ui/warning.ase"), None),
-            check: load_ase_texture(include_bytes!("../assets/ui/check.ase"), None),
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        match AssetPack::from_dir(&assets_dir()).and_then(|pack| {
+            let watcher = AssetWatcher::new(assets_dir(), &pack.manifest);
+            Ok((Self::from_pack(pack)?, watcher))
+        }) {
+            Ok((mut assets, watcher)) => {
+                assets.watcher = Some(RefCell::new(watcher));
+                return assets;
+            }
+            Err(e) => warn!("couldn't load assets from disk, using the embedded ones: {e}"),
+        }
+        Self::from_pack(AssetPack::embedded())
+            .unwrap_or_else(|e| panic!("embedded assets are invalid: {e}"))
+    }
+}
+impl Assets {
+    pub fn from_pack(pack: AssetPack) -> Result<Self, String> {
+        let assets = Self {
+            fonts: Default::default(),
+            textures: Default::default(),
+            animations: Default::default(),
+            animation_groups: Default::default(),
+            tilesets: Default::default(),
+            roles: RefCell::new(pack.manifest.roles.clone()),
+            pack: RefCell::new(pack),
+            generation: Cell::new(0),
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            watcher: None,
+        };
+        assets.load(&assets.pack.borrow(), None)?;
+        Ok(assets)
+    }
+    /// (Re)loads the assets of `pack` that use any of the `changed` files, or every asset if `None`.
+    ///
+    /// The registries are only changed if every asset loaded, otherwise the errors are returned.
+    fn load(&self, pack: &AssetPack, changed: Option<&[String]>) -> Result<(), String> {
+        let manifest = &pack.manifest;
+        let affected = |path: &String| changed.is_none_or(|f| f.contains(path));
+        let mut errors = Vec::new();
 
-            reload_btn: Animation::from_file(include_bytes!("../assets/ui/reload_btn.ase")),
-            sort_downloads_btn: Animation::from_file(include_bytes!(
-                "../assets/ui/sort_downloads_btn.ase"
-            )),
-            sort_name_btn: Animation::from_file(include_bytes!("../assets/ui/sort_name_btn.ase")),
-            sort_time_btn: Animation::from_file(include_bytes!("../assets/ui/sort_time_btn.ase")),
+        let mut fonts = Vec::new();
+        for f in manifest.fonts.iter().filter(|f| affected(&f.path)) {
+            let font = load_ttf_font_from_bytes(pack.file(&f.path))
+                .map(|mut font| {
+                    font.set_filter(FilterMode::Nearest);
+                    font
+                })
+                .map_err(|e| e.to_string());
+            stage(&mut fonts, &mut errors, &f.id, &f.path, font);
+        }
+        let mut textures = Vec::new();
+        for f in manifest.textures.iter().filter(|f| affected(&f.path)) {
+            let texture = load_ase_texture(pack.file(&f.path), f.layer);
+            stage(&mut textures, &mut errors, &f.id, &f.path, texture);
+        }
+        let mut animations = Vec::new();
+        for f in manifest.animations.iter().filter(|f| affected(&f.path)) {
+            let animation = Animation::from_file(pack.file(&f.path));
+            stage(&mut animations, &mut errors, &f.id, &f.path, animation);
+        }
+        let mut animation_groups = Vec::new();
+        for f in manifest
+            .animation_groups
+            .iter()
+            .filter(|f| affected(&f.path))
+        {
+            let group = AnimationsGroup::from_file(pack.file(&f.path));
+            stage(&mut animation_groups, &mut errors, &f.id, &f.path, group);
+        }
+        let mut tilesets = Vec::new();
+        for f in manifest
+            .tilesets
+            .iter()
+            .filter(|f| affected(&f.path) || f.autotiles.iter().any(affected))
+        {
+            let tileset = load_ase_texture(pack.file(&f.path), f.layer).and_then(|texture| {
+                f.autotiles
+                    .iter()
+                    .try_fold(Spritesheet::new(texture, 16.0), |tileset, path| {
+                        tileset
+                            .autotile(pack.file(path))
+                            .map_err(|e| format!("{path}: {e}"))
+                    })
+            });
+            stage(&mut tilesets, &mut errors, &f.id, &f.path, tileset);
+        }
+        if !errors.is_empty() {
+            return Err(errors.join(", "));
+        }
 
-            tool_btns: [
-                Animation::from_file(include_bytes!("../assets/ui/pencil_btn.ase")),
-                Animation::from_file(include_bytes!("../assets/ui/eraser_btn.ase")),
-                Animation::from_file(include_bytes!("../assets/ui/shape_btn.ase")),
-            ],
+        let replace = changed.is_none();
+        commit(&self.fonts, fonts, replace);
+        commit(&self.textures, textures, replace);
+        commit(&self.animations, animations, replace);
+        commit(&self.animation_groups, animation_groups, replace);
+        commit(&self.tilesets, tilesets, replace);
+        *self.roles.borrow_mut() = manifest.roles.clone();
+        Ok(())
+    }
+    /// Reloads the assets whose files changed on disk since they were last loaded.
+    ///
+    /// Files that fail to load are skipped, keeping their old assets, and tried again once they change.
+    /// Only does anything when the assets were loaded from disk, see [Assets::default].
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn hot_reload(&self) {
+        let Some(watcher) = &self.watcher else {
+            return;
+        };
+        let mut watcher = watcher.borrow_mut();
+        let changed = watcher.poll(&self.pack.borrow().manifest);
+        if changed.is_empty() {
+            return;
         }
+        let dir = assets_dir();
+        let mut reloaded = Vec::new();
+        if changed.iter().any(|f| f == MANIFEST_PATH) {
+            // assets could have been added, removed or moved around, so just reload everything
+            let result = AssetPack::from_dir(&dir).and_then(|pack| {
+                self.load(&pack, None)?;
+                Ok(pack)
+            });
+            match result {
+                Ok(pack) => {
+                    *self.pack.borrow_mut() = pack;
+                    for path in changed.iter() {
+                        watcher.reloaded(path);
+                    }
+                    reloaded = changed;
+                }
+                Err(e) => {
+                    if watcher.failed(MANIFEST_PATH) {
+                        warn!("couldn't reload assets: {e}");
+                    }
+                    // the other files are reloaded along with the manifest once it's fixed
+                    for path in changed.iter() {
+                        watcher.failed(path);
+                    }
+                }
+            }
+        } else {
+            for path in changed {
+                let result = std::fs::read(dir.join(&path))
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| {
+                        let old = self.pack.borrow_mut().set_file(&path, Cow::Owned(bytes));
+                        let result =
+                            self.load(&self.pack.borrow(), Some(std::slice::from_ref(&path)));
+                        // the file might have been read while it was still being written
+                        if result.is_err() {
+                            self.pack.borrow_mut().set_file(&path, old);
+                        }
+                        result
+                    });
+                match result {
+                    Ok(()) => {
+                        watcher.reloaded(&path);
+                        reloaded.push(path);
+                    }
+                    Err(e) => {
+                        if watcher.failed(&path) {
+                            warn!("couldn't reload {path}: {e}");
+                        }
+                    }
+                }
+            }
+        }
+        if !reloaded.is_empty() {
+            self.generation.set(self.generation.get() + 1);
+            info!("reloaded assets: {}", reloaded.join(", "));
+        }
+    }
+    /// Increased every time assets are reloaded
+    pub fn generation(&self) -> u32 {
+        self.generation.get()
+    }
+    /// The pack the assets were loaded from
+    pub fn pack(&self) -> Ref<'_, AssetPack> {
+        self.pack.borrow()
+    }
+    pub fn texture(&self, id: &str) -> &Texture2D {
+        get_asset(&self.textures, "texture", id)
+    }
+    pub fn animation(&self, id: &str) -> &Animation {
+        get_asset(&self.animations, "animation", id)
+    }
+    pub fn animation_group(&self, id: &str) -> &AnimationsGroup {
+        get_asset(&self.animation_groups, "animation group", id)
+    }
+    pub fn tileset(&self, id: &str) -> &Spritesheet {
+        get_asset(&self.tilesets, "tileset", id)
+    }
+    pub fn font(&self) -> &Font {
+        get_asset(&self.fonts, "font", &self.roles.borrow().font)
+    }
+    pub fn terrain_tileset(&self) -> &Spritesheet {
+        self.tileset(&self.roles.borrow().terrain)
+    }
+    pub fn obstacles_tileset(&self) -> &Spritesheet {
+        self.tileset(&self.roles.borrow().obstacles)
+    }
+    pub fn character_tileset(&self) -> &Spritesheet {
+        self.tileset(&self.roles.borrow().characters)
+    }
+    pub fn enemies(&self) -> &AnimationsGroup {
+        self.animation_group(&self.roles.borrow().enemies)
+    }
+    pub fn player_torso(&self) -> &AnimationsGroup {
+        self.animation_group(&self.roles.borrow().player_torso)
+    }
+    pub fn player_legs(&self) -> &AnimationsGroup {
+        self.animation_group(&self.roles.borrow().player_legs)
+    }
+    pub fn player_die(&self) -> &Texture2D {
+        self.texture(&self.roles.borrow().player_die)
+    }
+}
+fn leak<T>(asset: T) -> &'static T {
+    Box::leak(Box::new(asset))
+}
+/// Keeps a loaded asset to be put in its registry, or the error it failed to load with
+fn stage<T>(
+    staged: &mut Vec<(String, &'static T)>,
+    errors: &mut Vec<String>,
+    id: &str,
+    path: &str,
+    asset: Result<T, String>,
+) {
+    match asset {
+        Ok(asset) => staged.push((id.to_string(), leak(asset))),
+        Err(e) => errors.push(format!("{path}: {e}")),
     }
 }
-fn load_ase_texture(bytes: &[u8], layer: Option<u32>) -> Texture2D {
-    let img = AsepriteFile::read(bytes).unwrap();
+/// Puts loaded assets in their registry, replacing all of its assets or only the ones with the same ids
+fn commit<T>(registry: &Registry<T>, staged: Vec<(String, &'static T)>, replace: bool) {
+    let mut registry = registry.borrow_mut();
+    if replace {
+        registry.clear();
+    }
+    registry.extend(staged);
+}
+fn get_asset<T>(registry: &Registry<T>, kind: &str, id: &str) -> &'static T {
+    registry
+        .borrow()
+        .get(id)
+        .unwrap_or_else(|| panic!("no {kind} with the id {id}"))
+}
+fn load_ase_texture(bytes: &[u8], layer: Option<u32>) -> Result<Texture2D, String> {
+    let img = AsepriteFile::read(bytes).map_err(|e| e.to_string())?;
     let img = if let Some(layer) = layer {
+        if layer >= img.num_layers() {
+            return Err(format!("there's no layer {layer}"));
+        }
         img.layer(layer).frame(0).image()
     } else {
         img.frame(0).image()
@@ -141,7 +310,7 @@ fn load_ase_texture(bytes: &[u8], layer: Option<u32>) -> Texture2D {
     };
     let texture = Texture2D::from_image(&new);
     texture.set_filter(FilterMode::Nearest);
-    texture
+    Ok(texture)
 }
 pub struct AnimationsGroup {
     #[expect(dead_code)]
@@ -153,8 +322,8 @@ impl AnimationsGroup {
     pub fn get_by_name(&self, name: &str) -> &Animation {
         &self.animations[*self.tag_names.get(name).unwrap()]
     }
-    pub fn from_file(bytes: &[u8]) -> Self {
-        let ase = AsepriteFile::read(bytes).unwrap();
+    pub fn from_file(bytes: &[u8]) -> Result<Self, String> {
+        let ase = AsepriteFile::read(bytes).map_err(|e| e.to_string())?;
         let mut frames = Vec::new();
         for index in 0..ase.num_frames() {
             let frame = ase.frame(index);
@@ -191,59 +360,64 @@ impl AnimationsGroup {
                 total_length,
             });
         }
-        Self {
+        Ok(Self {
             file: ase,
             animations: tag_frames,
             tag_names,
-        }
+        })
     }
 }
-type AutotileData = (HashMap<[bool; 4], Vec2>, Box<Spritesheet>);
 pub struct Spritesheet {
     pub texture: Texture2D,
     pub sprite_size: f32,
-    /// Special case, if true, first tile of the spritesheet is autotiled.
-    pub autotile_first: Option<AutotileData>,
+    /// Tiles of the spritesheet that are autotiled
+    pub autotiles: Vec<Autotile>,
 }
 impl Spritesheet {
     pub fn new(texture: Texture2D, sprite_size: f32) -> Self {
         Self {
             texture,
             sprite_size,
-            autotile_first: None,
+            autotiles: Vec::new(),
         }
     }
-    pub fn autotile(
-        mut self,
-        autotiling_tileset: Texture2D,
-        autotiling_ruleset: Texture2D,
-    ) -> Self {
-        let mut hashmap = HashMap::new();
-        let image = autotiling_ruleset.get_texture_data();
-        for x in 0..(autotiling_tileset.width() as u32) / 16 {
-            let x = x * 16;
-            for y in 0..(autotiling_tileset.height() as u32) / 16 {
-                let y = y * 16;
-                let [r, g, b, a] = image.bytes[x as usize * 4
-                    + y as usize * 4 * image.width as usize
-                    ..x as usize * 4 + y as usize * 4 * image.width as usize + 4]
-                else {
-                    panic!("._.")
-                };
-                if a == 0 {
-                    continue;
+    /// Returns the position of every tile in the spritesheet, up until the first empty tile
+    pub fn get_tiles(&self) -> Vec<Vec2> {
+        let image = self.texture.get_texture_data();
+        let mut tiles = Vec::new();
+        'outer: for y in 0..self.texture.height() as u32 / 16 {
+            let y = y as f32 * 16.0;
+            for x in 0..self.texture.width() as u32 / 16 {
+                let x = x as f32 * 16.0;
+                let area = image.sub_image(Rect {
+                    x,
+                    y,
+                    w: 16.0,
+                    h: 16.0,
+                });
+                let mut empty = true;
+                for pixel in area.get_image_data().iter() {
+                    if pixel[3] != 0 {
+                        empty = false;
+                        break;
+                    }
                 }
-                hashmap.insert(
-                    [r == 255, g == 255, b == 255, a == 255],
-                    vec2(x as f32, y as f32),
-                );
+                if empty {
+                    break 'outer;
+                }
+                tiles.push(vec2(x / 16.0, y / 16.0));
             }
         }
-        self.autotile_first = Some((
-            hashmap,
-            Box::new(Spritesheet::new(autotiling_tileset, 16.0)),
-        ));
-        self
+        tiles
+    }
+    /// Adds an autotiled tile to the spritesheet, from an autotile sheet (see [crate::autotile])
+    pub fn autotile(mut self, bytes: &[u8]) -> Result<Self, String> {
+        self.autotiles.push(Autotile::from_file(bytes)?);
+        Ok(self)
+    }
+    /// The autotiling rules of a tile id, if it is autotiled
+    pub fn autotile_for(&self, tile: u8) -> Option<&Autotile> {
+        self.autotiles.iter().find(|f| f.rules.tile == tile)
     }
     #[expect(dead_code)]
     /// Same as `draw_tile`, except centered
@@ -291,8 +465,8 @@ pub struct Animation {
     pub total_length: u32,
 }
 impl Animation {
-    pub fn from_file(bytes: &[u8]) -> Self {
-        let ase = AsepriteFile::read(bytes).unwrap();
+    pub fn from_file(bytes: &[u8]) -> Result<Self, String> {
+        let ase = AsepriteFile::read(bytes).map_err(|e| e.to_string())?;
         let mut frames = Vec::new();
         let mut total_length = 0;
         for index in 0..ase.num_frames() {
@@ -309,10 +483,13 @@ impl Animation {
             texture.set_filter(FilterMode::Nearest);
             frames.push((texture, duration));
         }
-        Self {
+        if total_length == 0 {
+            return Err("animation has no length".to_string());
+        }
+        Ok(Self {
             frames,
             total_length,
-        }
+        })
     }
     pub fn get_at_time(&self, mut time: u32) -> &Texture2D {
         time %= self.total_length;
@@ -0,0 +1,260 @@
+//! Mod for autotiling, picking which variant of a tile to draw based on its neighbours.
+//!
+//! Autotiled tiles are defined by aseprite files with two layers. The first holds the variants of the tile,
+//! and a layer named `rules` marks which neighbours each variant is meant for. The top left 3x3 pixels of
+//! each 16x16 variant are a map of its neighbourhood: the center pixel marks the variant as used,
+//! and every other opaque pixel marks that neighbour as connected.
+//!
+//! The user data of the `rules` layer holds the [AutotileSettings] as RON, like `(tile: 1, blends_with: [2])`.
+//!
+//! Variants for the corners are optional. A corner only counts as connected when both sides next to it are,
+//! and if there's no variant for the exact neighbourhood, the corners are ignored. A sheet with the 16
+//! side-only variants therefore works the same as a full 47 tile blob set, just with rougher inner corners.
+//! That's all `terrain.ase` has for now, and it doesn't blend with any other tile.
+
+use std::collections::HashMap;
+
+use asefile::AsepriteFile;
+use image::RgbaImage;
+use macroquad::prelude::*;
+use nanoserde::DeRon;
+
+use crate::{assets::Spritesheet, level::Level};
+
+/// Offsets of the neighbours of a tile, in the order of the bits of a neighbour mask
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+/// Bits of the neighbour mask for the sides (up, left, right, down)
+const SIDES_MASK: u8 = 0b01011010;
+/// Each corner bit of the neighbour mask, along with the bits of the two sides next to it
+const CORNERS: [(u8, u8); 4] = [
+    (0b00000001, 0b00001010),
+    (0b00000100, 0b00010010),
+    (0b00100000, 0b01001000),
+    (0b10000000, 0b01010000),
+];
+
+#[derive(DeRon)]
+struct AutotileSettings {
+    /// The tile id (as stored in levels) that is autotiled
+    tile: u8,
+    /// Other tile ids of the same layer that the tile connects to, as if they were the same tile
+    #[nserde(default)]
+    blends_with: Vec<u8>,
+}
+
+/// Which variant of an autotiled tile to use for each neighbourhood.
+///
+/// Kept apart from the textures, so it can also be used without a GPU (see [crate::thumbnail]).
+pub struct AutotileRules {
+    pub tile: u8,
+    pub blends_with: Vec<u8>,
+    /// Tile position of the variant to use for every neighbour mask
+    variants: HashMap<u8, Vec2>,
+}
+impl AutotileRules {
+    /// Reads the rules of an autotile sheet, along with the image of its variants
+    pub fn from_file(bytes: &[u8]) -> Result<(Self, RgbaImage), String> {
+        let ase = AsepriteFile::read(bytes).map_err(|e| e.to_string())?;
+        let rules_layer = ase
+            .layer_by_name("rules")
+            .ok_or("autotile sheet is missing a rules layer")?;
+        let settings = rules_layer
+            .user_data()
+            .and_then(|f| f.text.as_deref())
+            .ok_or("autotile rules layer is missing settings in its user data")?;
+        let AutotileSettings { tile, blends_with } = DeRon::deserialize_ron(settings)
+            .map_err(|e| format!("invalid autotile settings: {e}"))?;
+
+        let rules = rules_layer.frame(0).image();
+        let mut variants = HashMap::new();
+        for y in 0..rules.height() / 16 {
+            for x in 0..rules.width() / 16 {
+                let opaque = |dx: isize, dy: isize| {
+                    rules.get_pixel(x * 16 + (1 + dx) as u32, y * 16 + (1 + dy) as u32)[3] != 0
+                };
+                if !opaque(0, 0) {
+                    continue;
+                }
+                let mut mask = 0;
+                for (bit, (dx, dy)) in NEIGHBOURS.into_iter().enumerate() {
+                    if opaque(dx, dy) {
+                        mask |= 1 << bit;
+                    }
+                }
+                variants.insert(canonical_mask(mask), vec2(x as f32, y as f32));
+            }
+        }
+
+        let tiles = ase
+            .layers()
+            .find(|f| f.id() != rules_layer.id())
+            .ok_or("autotile sheet is missing a layer with its tiles")?
+            .frame(0)
+            .image();
+
+        Ok((
+            Self {
+                tile,
+                blends_with,
+                variants,
+            },
+            tiles,
+        ))
+    }
+    /// Whether a tile next to this one connects to it
+    pub fn connects(&self, tile: u8) -> bool {
+        tile == self.tile || self.blends_with.contains(&tile)
+    }
+    /// Builds the neighbour mask for a tile of the level.
+    ///
+    /// Past the top and left edges of the level, the edge row or column is repeated, while everything past
+    /// the right and bottom edges is empty. This is how terrain was always drawn, so ground running into
+    /// the top or left edge looks cut off, and ground at the right or bottom edge gets its outline.
+    pub fn neighbour_mask(&self, level: &Level, layer: usize, x: usize, y: usize) -> u8 {
+        let mut mask = 0;
+        for (bit, (dx, dy)) in NEIGHBOURS.into_iter().enumerate() {
+            let (nx, ny) = (x.saturating_add_signed(dx), y.saturating_add_signed(dy));
+            if self.connects(level.get_tile(nx, ny)[layer]) {
+                mask |= 1 << bit;
+            }
+        }
+        canonical_mask(mask)
+    }
+    /// Tile position of the variant to draw for a neighbour mask, if the sheet has one
+    pub fn variant(&self, mask: u8) -> Option<Vec2> {
+        self.variants
+            .get(&mask)
+            .or_else(|| self.variants.get(&(mask & SIDES_MASK)))
+            .copied()
+    }
+}
+
+pub struct Autotile {
+    pub rules: AutotileRules,
+    pub spritesheet: Spritesheet,
+}
+impl Autotile {
+    pub fn from_file(bytes: &[u8]) -> Result<Self, String> {
+        let (rules, tiles) = AutotileRules::from_file(bytes)?;
+        let texture = Texture2D::from_rgba8(tiles.width() as u16, tiles.height() as u16, &tiles);
+        texture.set_filter(FilterMode::Nearest);
+        Ok(Self {
+            rules,
+            spritesheet: Spritesheet::new(texture, 16.0),
+        })
+    }
+}
+
+/// Clears the corners of a neighbour mask that don't matter, because a side next to them isn't connected
+fn canonical_mask(mut mask: u8) -> u8 {
+    for (corner, sides) in CORNERS {
+        if mask & sides != sides {
+            mask &= !corner;
+        }
+    }
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terrain_rules() -> AutotileRules {
+        AutotileRules::from_file(include_bytes!("../assets/terrain.ase"))
+            .unwrap()
+            .0
+    }
+
+    /// A level of one tile layer, from rows of `#` (tile 1), `+` (tile 2) and `.` (empty)
+    fn parse_level(rows: &[&str]) -> Level {
+        let tiles = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|f| match f {
+                '#' => [1, 0],
+                '+' => [2, 0],
+                _ => [0, 0],
+            })
+            .collect();
+        Level {
+            tiles,
+            width: rows[0].len(),
+            characters: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn canonical_masks() {
+        assert_eq!(canonical_mask(0), 0);
+        assert_eq!(canonical_mask(0xff), 0xff);
+        assert_eq!(canonical_mask(SIDES_MASK), SIDES_MASK);
+        // corners without both of their sides are dropped
+        assert_eq!(canonical_mask(!SIDES_MASK), 0);
+        assert_eq!(canonical_mask(0b00000011), 0b00000010);
+        assert_eq!(canonical_mask(0b00001011), 0b00001011);
+        assert_eq!(canonical_mask(0b11011010), 0b11011010);
+        assert_eq!(canonical_mask(0b11111010), 0b11111010);
+        assert_eq!(canonical_mask(0b11110010), 0b11010010);
+    }
+
+    #[test]
+    fn reads_rules_from_sheet() {
+        let (rules, tiles) =
+            AutotileRules::from_file(include_bytes!("../assets/terrain.ase")).unwrap();
+        assert_eq!(rules.tile, 1);
+        assert!(rules.blends_with.is_empty());
+        assert_eq!(rules.variants.len(), 16);
+        assert_eq!((tiles.width(), tiles.height()), (112, 80));
+        assert!(rules.variants.keys().all(|f| canonical_mask(*f) == *f));
+    }
+
+    #[test]
+    fn variants_fall_back_to_sides() {
+        let rules = terrain_rules();
+        // the terrain sheet only has side variants, so every corner falls back to them
+        for mask in 0..=255 {
+            let mask = canonical_mask(mask);
+            assert_eq!(rules.variant(mask), rules.variant(mask & SIDES_MASK));
+            assert!(rules.variant(mask).is_some());
+        }
+        let empty = AutotileRules {
+            tile: 1,
+            blends_with: Vec::new(),
+            variants: HashMap::new(),
+        };
+        assert_eq!(empty.variant(0xff), None);
+    }
+
+    #[test]
+    fn neighbour_masks() {
+        let rules = terrain_rules();
+        let level = parse_level(&["...", ".#.", "..."]);
+        assert_eq!(rules.neighbour_mask(&level, 0, 1, 1), 0);
+
+        let level = parse_level(&["##.", "###"]);
+        // the top and left edges are repeated
+        assert_eq!(rules.neighbour_mask(&level, 0, 0, 0), 0xff);
+        assert_eq!(rules.neighbour_mask(&level, 0, 1, 0), 0b01101011);
+        // while the right and bottom edges are empty
+        assert_eq!(rules.neighbour_mask(&level, 0, 2, 1), 0b00001000);
+        assert_eq!(rules.neighbour_mask(&level, 0, 1, 1), 0b00011011);
+    }
+
+    #[test]
+    fn blends_with_other_tiles() {
+        let mut rules = terrain_rules();
+        let level = parse_level(&["#+"]);
+        assert_eq!(rules.neighbour_mask(&level, 0, 0, 0), 0b00001011);
+        rules.blends_with.push(2);
+        assert_eq!(rules.neighbour_mask(&level, 0, 0, 0), 0b00011111);
+    }
+}
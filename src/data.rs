@@ -1,13 +1,32 @@
 //! Mod for handling both local level saving, as well as fetching online levels
 
-use std::{collections::HashMap, fs::read, path::PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::{collections::HashMap, fs::read};
 
 use base64::{Engine, prelude::BASE64_STANDARD};
 use macroquad::prelude::warn;
 use nanoserde::{DeBin, SerBin};
 use quad_net::http_request::Request;
 
-use crate::{level::Level, utils::DEBUG_ARGS};
+use crate::{
+    format::{
+        decode_level, decode_local_data, decode_recovery, decode_snapshots, encode_level,
+        encode_local_data, encode_recovery, encode_snapshots,
+    },
+    level::Level,
+    utils::{DEBUG_ARGS, MAX_LEVEL_NAME_LENGTH, MAX_LEVEL_SNAPSHOTS, MAX_SNAPSHOTS_SIZE},
+};
+
+/// Storage keys of the parts of the save data. On native, these are the names of files next to the game.
+const SAVE_KEY: &str = "save.wa";
+const RECOVERY_KEY: &str = "recovery.wa";
+const SNAPSHOTS_KEY: &str = "snapshots.wa";
+
+/// Level open in the editor, along with its name if it has one
+pub type Recovery = (Option<String>, Level);
+/// Saved versions of local levels, by level name. Each is timestamped (unix seconds) and oldest first.
+pub type Snapshots = Vec<(String, Vec<(u64, Level)>)>;
 
 pub enum NetworkResult {
     Success,
@@ -21,6 +40,17 @@ pub enum LevelSorting {
 }
 pub struct Data {
     pub local: LocalData,
+    /// Autosave of the level open in the editor, set through [Data::set_recovery].
+    /// Cleared when the editor is exited normally, so if this is set on launch, the game didn't close cleanly.
+    pub recovery: Option<Recovery>,
+    /// Previously saved versions of local levels
+    snapshots: Snapshots,
+    /// The snapshots changed since they were last stored
+    snapshots_changed: bool,
+    /// Why storing the save data last failed, until it is shown to the player
+    pub store_error: Option<String>,
+    /// Bumped every time the local data is stored, so anything cached from the local levels knows to refresh
+    pub local_generation: u32,
     pub online_levels: Vec<(String, u32, String, u64)>,
     pub cached_online_levels: HashMap<String, Level>,
     pub list_request: Option<Request>,
@@ -33,29 +63,134 @@ pub struct Data {
     pub sorting: LevelSorting,
 }
 impl Data {
+    /// Checks a name for a local level against the names of the other local levels.
+    /// Returns the name with surrounding whitespace removed, or why it can't be used.
+    ///
+    /// Renaming and saving as a new level both go through this, so they follow the same rules.
+    pub fn check_level_name<'a>(
+        name: &str,
+        mut others: impl Iterator<Item = &'a str>,
+    ) -> Result<String, &'static str> {
+        let name = name.trim();
+        if name.is_empty() {
+            Err("Level name can't be empty")
+        } else if others.any(|f| f == name) {
+            Err("A level with this name exists")
+        } else {
+            Ok(name.to_string())
+        }
+    }
     pub fn rename_level(&mut self, index: usize, new_name: String) -> bool {
-        if !self
+        let others = self
             .local
             .user_levels
             .iter()
             .enumerate()
             .filter(|(i, _)| &index != i)
-            .any(|(_, f)| f.0 == new_name)
-        {
-            let old_name = &self.local.user_levels[index].0;
-            if let Some(value) = self.verified_levels.remove(old_name) {
-                self.verified_levels.insert(new_name.clone(), value);
-            }
-            self.local.user_levels[index].0 = new_name;
-            true
+            .map(|(_, f)| f.0.as_str());
+        let Ok(new_name) = Self::check_level_name(&new_name, others) else {
+            return false;
+        };
+        let old_name = &self.local.user_levels[index].0;
+        if let Some(value) = self.verified_levels.remove(old_name) {
+            self.verified_levels.insert(new_name.clone(), value);
+        }
+        if let Some(snapshots) = self.snapshots.iter_mut().find(|f| &f.0 == old_name) {
+            snapshots.0 = new_name.clone();
+            self.snapshots_changed = true;
+        }
+        self.local.user_levels[index].0 = new_name;
+        true
+    }
+    /// Adds a copy of a local level under a free name. Returns the name of the copy.
+    pub fn duplicate_level(&mut self, index: usize) -> String {
+        let (name, level) = self.local.user_levels[index].clone();
+        let new_name = self.unique_level_name(&name);
+        if let Some(verified) = self.verified_levels.get(&name) {
+            self.verified_levels.insert(new_name.clone(), *verified);
+        }
+        self.local.user_levels.push((new_name.clone(), level));
+        new_name
+    }
+    /// Deletes a local level along with its history
+    pub fn delete_level(&mut self, index: usize) {
+        let (name, _) = self.local.user_levels.remove(index);
+        self.snapshots.retain(|f| f.0 != name);
+        self.snapshots_changed = true;
+    }
+    /// Saved versions of a local level, oldest first
+    pub fn level_snapshots(&self, name: &str) -> &[(u64, Level)] {
+        self.snapshots
+            .iter()
+            .find(|f| f.0 == name)
+            .map(|f| &f.1[..])
+            .unwrap_or(&[])
+    }
+    /// Adds a level to the history of the local level by that name, unless it is unchanged since the last snapshot.
+    /// Only the newest [MAX_LEVEL_SNAPSHOTS] are kept, and the oldest snapshots of all levels are dropped
+    /// when they are stored if they take up more than [MAX_SNAPSHOTS_SIZE].
+    pub fn snapshot_level(&mut self, name: &str, level: &Level) {
+        let timestamp = macroquad::miniquad::date::now() as u64;
+        let snapshots = if let Some(index) = self.snapshots.iter().position(|f| f.0 == name) {
+            &mut self.snapshots[index].1
         } else {
-            false
+            self.snapshots.push((name.to_string(), Vec::new()));
+            &mut self.snapshots.last_mut().unwrap().1
+        };
+        if snapshots.last().is_some_and(|f| &f.1 == level) {
+            return;
+        }
+        snapshots.push((timestamp, level.clone()));
+        if snapshots.len() > MAX_LEVEL_SNAPSHOTS {
+            snapshots.remove(0);
+        }
+        self.snapshots_changed = true;
+    }
+    /// Stores the local data, and the snapshots if they changed.
+    /// Failures are logged and kept in [Data::store_error].
+    pub fn store(&mut self) {
+        self.local_generation = self.local_generation.wrapping_add(1);
+        let mut result = write_storage(SAVE_KEY, Some(&encode_local_data(&self.local)));
+        if result.is_ok() && self.snapshots_changed {
+            let encoded = encode_bounded_snapshots(&mut self.snapshots, MAX_SNAPSHOTS_SIZE);
+            result = write_storage(SNAPSHOTS_KEY, Some(&encoded));
+            self.snapshots_changed = result.is_err();
+        }
+        self.report_store(result);
+    }
+    /// Sets the recovery slot and stores only it, so autosaving doesn't rewrite the rest of the save data
+    pub fn set_recovery(&mut self, recovery: Option<Recovery>) {
+        let encoded = recovery.as_ref().map(encode_recovery);
+        self.recovery = recovery;
+        let result = write_storage(RECOVERY_KEY, encoded.as_deref());
+        self.report_store(result);
+    }
+    fn report_store(&mut self, result: Result<(), String>) {
+        if let Err(e) = result {
+            warn!("couldn't store save data: {e}");
+            self.store_error = Some(e);
+        }
+    }
+    /// Returns `name`, with a number appended if a local level by that name already exists
+    pub fn unique_level_name(&self, name: &str) -> String {
+        let taken = |name: &str| self.local.user_levels.iter().any(|f| f.0 == name);
+        if !taken(name) {
+            return name.to_string();
+        }
+        let mut i = 1;
+        loop {
+            i += 1;
+            let suffix = format!(" {i}");
+            let mut new_name = name.to_string();
+            new_name.truncate(MAX_LEVEL_NAME_LENGTH - suffix.len());
+            new_name += &suffix;
+            if !taken(&new_name) {
+                return new_name;
+            }
         }
     }
     pub fn upload_level(&mut self, level: Level, name: String, author: String) {
-        let mut buffer = Vec::new();
-        level.ser_bin(&mut buffer);
-        let base64 = BASE64_STANDARD.encode(buffer);
+        let base64 = BASE64_STANDARD.encode(encode_level(&level));
         self.uploading = Some(
             quad_net::http_request::RequestBuilder::new(&format!(
                 "{}/upload/{name}-{author}",
@@ -85,8 +220,23 @@ impl Data {
         );
     }
     pub fn load() -> Self {
-        Self {
-            local: LocalData::load(),
+        let (local, moved) = read_storage(SAVE_KEY)
+            .and_then(|buffer| decode_local_data(&buffer).ok())
+            .unwrap_or_default();
+        let recovery = read_storage(RECOVERY_KEY).and_then(|buffer| decode_recovery(&buffer).ok());
+        let snapshots =
+            read_storage(SNAPSHOTS_KEY).and_then(|buffer| decode_snapshots(&buffer).ok());
+        // saves from before save version 7 kept these in the save data, so they are moved to their own keys
+        // before the save data is stored without them
+        let snapshots_changed = snapshots.is_none() && !moved.snapshots.is_empty();
+        let moved_recovery = recovery.is_none() && moved.recovery.is_some();
+        let mut data = Self {
+            local,
+            recovery: recovery.or(moved.recovery),
+            snapshots: snapshots.unwrap_or(moved.snapshots),
+            snapshots_changed,
+            store_error: None,
+            local_generation: 0,
             online_levels: Vec::new(),
             cached_online_levels: HashMap::new(),
             fetch_requests: Vec::new(),
@@ -102,8 +252,14 @@ impl Data {
             download_result: None,
             verified_levels: HashMap::new(),
             sorting: LevelSorting::Downloads,
+        };
+        if moved_recovery {
+            data.set_recovery(data.recovery.clone());
         }
+        data
     }
+    // kept as it was, newer clippy versions prefer `sort_by_key` here
+    #[allow(clippy::unnecessary_sort_by)]
     pub fn sort(&mut self) {
         match self.sorting {
             LevelSorting::Downloads => {
@@ -170,9 +326,6 @@ impl Data {
             if let Some(result) = request.try_recv() {
                 match result {
                     Ok(data) => {
-                        fn deserialize(buffer: &[u8]) -> Option<Level> {
-                            Level::de_bin(&mut 0, buffer).ok()
-                        }
                         if data.starts_with("error:") {
                             let msg = data.trim_start_matches("error:").to_string();
                             self.download_result =
@@ -184,14 +337,14 @@ impl Data {
                                     Some((name.to_string(), NetworkResult::Fail(e.to_string())));
                                 return false;
                             };
-                            let Some(level) = deserialize(&decoded) else {
-                                let e = format!(
-                                    "level '{}' couldn't be deserialized to level data",
-                                    *name
-                                );
-                                self.download_result =
-                                    Some((name.to_string(), NetworkResult::Fail(e.to_string())));
-                                return false;
+                            let level = match decode_level(&decoded) {
+                                Ok(level) => level,
+                                Err(e) => {
+                                    let e = format!("level '{}' couldn't be loaded: {e}", *name);
+                                    self.download_result =
+                                        Some((name.to_string(), NetworkResult::Fail(e)));
+                                    return false;
+                                }
                             };
                             self.cached_online_levels.insert(name.clone(), level);
                             self.download_result = Some((name.to_string(), NetworkResult::Success));
@@ -214,66 +367,168 @@ impl Data {
 pub struct LocalData {
     pub user_levels: Vec<(String, Level)>,
     pub completed_online_levels: Vec<String>,
+    /// Level pieces saved in the editor for reuse, with character positions relative to the top left corner
+    pub stamps: Vec<(String, Level)>,
 }
 impl LocalData {
-    fn get_save_path() -> Option<PathBuf> {
-        let path = std::env::current_exe().ok()?;
-        let parent = path.parent()?;
-        let path = parent.join("save.wa");
-        Some(path)
+    /// Directory that levels are exported to and imported from
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_export_dir() -> Result<PathBuf, String> {
+        let path = std::env::current_exe().map_err(|e| e.to_string())?;
+        let parent = path.parent().ok_or("game directory not found")?;
+        Ok(parent.join("levels"))
     }
-    pub fn load() -> Self {
-        fn deserialize(buffer: &[u8]) -> Option<LocalData> {
-            LocalData::de_bin(&mut 0, buffer).ok()
-        }
+    /// Writes a level as text to a directory (see [LocalData::get_export_dir]), returning the path written to
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_level(dir: &Path, name: &str, level: &Level) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let path = dir.join(format!("{name}.ron"));
+        std::fs::write(&path, crate::format::level_to_text(level)).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+    /// Reads a level by file name (without extension) from a directory (see [LocalData::get_export_dir])
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn import_level(dir: &Path, name: &str) -> Result<Level, String> {
+        let Ok(text) = std::fs::read_to_string(dir.join(format!("{name}.ron"))) else {
+            return Err(format!("levels/{name}.ron couldn't be read"));
+        };
+        crate::format::level_from_text(&text).map_err(|e| e.to_string())
+    }
+}
 
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            let Some(path) = Self::get_save_path() else {
-                return Self::default();
-            };
-            let Ok(buffer) = read(path) else {
-                return Self::default();
-            };
-            deserialize(&buffer).unwrap_or_default()
+/// Encodes the snapshots, dropping the oldest ones of any level until they fit in `max_size` bytes
+fn encode_bounded_snapshots(snapshots: &mut Snapshots, max_size: usize) -> Vec<u8> {
+    loop {
+        let encoded = encode_snapshots(snapshots);
+        let oldest = snapshots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, f)| Some((i, f.1.first()?.0)))
+            .min_by_key(|f| f.1);
+        let Some((index, _)) = oldest.filter(|_| encoded.len() > max_size) else {
+            return encoded;
+        };
+        snapshots[index].1.remove(0);
+        if snapshots[index].1.is_empty() {
+            snapshots.remove(index);
         }
+    }
+}
 
-        #[cfg(target_arch = "wasm32")]
-        {
-            let storage = quad_storage::LocalStorage::default();
+#[cfg(not(target_arch = "wasm32"))]
+fn get_storage_path(key: &str) -> Option<PathBuf> {
+    let path = std::env::current_exe().ok()?;
+    let parent = path.parent()?;
+    Some(parent.join(key))
+}
 
-            let Some(data) = storage.get("save.wa") else {
-                return Self::default();
-            };
-            let Ok(buffer) = BASE64_STANDARD.decode(&data) else {
-                return Self::default();
-            };
-            deserialize(&buffer).unwrap_or_default()
-        }
+/// Reads a part of the save data. On native it is a file next to the game, on web an entry of the browser's local storage.
+fn read_storage(key: &str) -> Option<Vec<u8>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        read(get_storage_path(key)?).ok()
     }
-    pub fn store(&self) {
-        fn serialize(data: &LocalData) -> Vec<u8> {
-            let mut buffer = Vec::new();
-            data.ser_bin(&mut buffer);
-            buffer
-        }
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            use std::fs::write;
 
-            let Some(path) = Self::get_save_path() else {
-                return;
-            };
-            let data = serialize(self);
-            write(path, data).unwrap();
-        }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let storage = quad_storage::LocalStorage::default();
+        BASE64_STANDARD.decode(storage.get(key)?).ok()
+    }
+}
 
-        #[cfg(target_arch = "wasm32")]
-        {
-            let mut storage = quad_storage::LocalStorage::default();
-            let data = serialize(&self);
-            let buffer = BASE64_STANDARD.encode(&data);
-            storage.set("save.wa", &buffer);
+/// Writes a part of the save data, or removes it if `data` is `None`
+fn write_storage(key: &str, data: Option<&[u8]>) -> Result<(), String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let path = get_storage_path(key).ok_or("game directory not found")?;
+        let result = match data {
+            Some(data) => std::fs::write(path, data),
+            None => std::fs::remove_file(path).or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            }),
+        };
+        result.map_err(|e| format!("{key}: {e}"))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let mut storage = quad_storage::LocalStorage::default();
+        match data {
+            Some(data) => {
+                let buffer = BASE64_STANDARD.encode(data);
+                storage.set(key, &buffer);
+                // the browser doesn't report running out of storage, so check that it was written
+                if storage.get(key).as_deref() != Some(buffer.as_str()) {
+                    return Err(format!("{key}: browser storage is full"));
+                }
+            }
+            None => storage.remove(key),
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::test_levels::test_level;
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_import_round_trip() {
+        let dir = std::env::temp_dir().join(format!("goblin-maker-export-{}", std::process::id()));
+        let level = test_level();
+        let exported = LocalData::export_level(&dir, "round trip", &level);
+        let imported = LocalData::import_level(&dir, "round trip");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(exported.unwrap(), dir.join("round trip.ron"));
+        assert!(imported.is_ok_and(|f| f == level));
+    }
+
+    #[test]
+    fn level_name_rules() {
+        let names = ["foo", "bar"];
+        let check = |name: &str| Data::check_level_name(name, names.iter().copied());
+        assert_eq!(check("baz"), Ok("baz".to_string()));
+        assert_eq!(check(" baz  "), Ok("baz".to_string()));
+        assert!(check("foo").is_err());
+        assert!(check(" foo").is_err());
+        assert!(check("foo ").is_err());
+        assert!(check("").is_err());
+        assert!(check("   ").is_err());
+    }
+
+    #[test]
+    fn snapshots_are_bounded_oldest_first() {
+        let mut level = test_level();
+        let mut snapshots: Snapshots = vec![
+            (
+                "a".to_string(),
+                vec![(1, level.clone()), (4, level.clone())],
+            ),
+            ("b".to_string(), vec![(2, level.clone())]),
+        ];
+        // a level that doesn't compress well, so it's much larger than the others
+        level
+            .tiles
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, f)| f[0] = i as u8);
+        snapshots[0].1[1].1 = level;
+        let unbounded = encode_bounded_snapshots(&mut snapshots.clone(), usize::MAX);
+        assert!(unbounded == encode_snapshots(&snapshots));
+        let bounded = encode_bounded_snapshots(&mut snapshots, unbounded.len() - 1);
+        assert!(bounded.len() < unbounded.len());
+        let timestamps = |snapshots: &Snapshots| -> Vec<u64> {
+            snapshots
+                .iter()
+                .flat_map(|f| f.1.iter().map(|f| f.0))
+                .collect()
+        };
+        assert_eq!(timestamps(&snapshots), [4, 2]);
+        // the newest snapshots are dropped too if they don't fit
+        encode_bounded_snapshots(&mut snapshots, 0);
+        assert!(snapshots.is_empty());
     }
 }
@@ -0,0 +1,942 @@
+//! Mod for the versioned binary format that levels and save files are stored in.
+//!
+//! Every payload is wrapped in an envelope of four magic bytes followed by a little endian
+//! `u16` format version. Payloads written before the envelope existed have no magic bytes,
+//! and are treated as version 0.
+//!
+//! If the highest bit of the version is set, the payload is run-length encoded (see [compress]).
+//! The mostly empty tile grids of levels shrink massively from this.
+//!
+//! The autosave recovery slot and level snapshots are stored on their own (see [encode_recovery]
+//! and [encode_snapshots]), so the frequent autosaves don't rewrite every local level.
+//!
+//! When the layout of [Level], [Character](crate::level::Character) or [LocalData] changes,
+//! bump the matching version constant, keep a frozen copy of the old layout around
+//! and add a migration step from it to [migrate_level] / [migrate_local_data].
+//!
+//! Levels can also be converted to and from a human readable RON representation,
+//! for exporting, diffing and hand editing.
+
+use std::{borrow::Cow, fmt::Display};
+
+use nanoserde::{DeBin, DeRon, SerBin, SerRon};
+
+use crate::{
+    data::{LocalData, Recovery, Snapshots},
+    level::{Character, Level},
+    utils::{DEFAULT_ENEMY_SPEED, MAX_LEVEL_NAME_LENGTH, MAX_LEVEL_SIZE},
+};
+
+const LEVEL_MAGIC: [u8; 4] = *b"GBLV";
+const SAVE_MAGIC: [u8; 4] = *b"GBSV";
+const RECOVERY_MAGIC: [u8; 4] = *b"GBRC";
+const SNAPSHOTS_MAGIC: [u8; 4] = *b"GBSN";
+
+/// Current version of the level format.
+pub const LEVEL_VERSION: u16 = 3;
+/// Current version of the save file format.
+pub const SAVE_VERSION: u16 = 7;
+/// Current version of the recovery slot format.
+pub const RECOVERY_VERSION: u16 = 1;
+/// Current version of the snapshots format.
+pub const SNAPSHOTS_VERSION: u16 = 1;
+
+/// Flag set in the version of an envelope when its payload is compressed.
+const COMPRESSED_FLAG: u16 = 1 << 15;
+
+/// Largest level payload accepted: two bytes for every tile of a level of the largest size,
+/// with plenty of room left for characters.
+const MAX_LEVEL_PAYLOAD: usize = MAX_LEVEL_SIZE * MAX_LEVEL_SIZE * 8;
+/// Largest save data payload accepted, which holds all local levels.
+const MAX_SAVE_PAYLOAD: usize = MAX_LEVEL_PAYLOAD * 16;
+/// Largest snapshots payload accepted. Far more than is ever stored (see [MAX_SNAPSHOTS_SIZE](crate::utils::MAX_SNAPSHOTS_SIZE)),
+/// as snapshots stored by save version 6 weren't bounded.
+const MAX_SNAPSHOTS_PAYLOAD: usize = MAX_SAVE_PAYLOAD;
+
+pub enum FormatError {
+    /// Payload was written by a newer version of the game
+    TooNew(u16),
+    /// Payload couldn't be deserialized
+    Corrupt,
+    /// Text representation couldn't be parsed
+    Malformed(String),
+}
+impl Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::TooNew(version) => write!(f, "made with a newer game (format v{version})"),
+            FormatError::Corrupt => write!(f, "data is corrupt"),
+            FormatError::Malformed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Run-length encodes data, using the PackBits scheme.
+///
+/// Each chunk starts with a header byte `n`. If `n` is at most 127, the next `n + 1` bytes
+/// are copied as is. Otherwise the next byte is repeated `257 - n` times.
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    let mut literals: Vec<u8> = Vec::new();
+    fn flush(buffer: &mut Vec<u8>, literals: &mut Vec<u8>) {
+        if !literals.is_empty() {
+            buffer.push(literals.len() as u8 - 1);
+            buffer.append(literals);
+        }
+    }
+
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        let run = data[i..]
+            .iter()
+            .take(128)
+            .take_while(|f| **f == byte)
+            .count();
+        if run >= 2 {
+            flush(&mut buffer, &mut literals);
+            buffer.push((257 - run) as u8);
+            buffer.push(byte);
+        } else {
+            literals.push(byte);
+            if literals.len() == 128 {
+                flush(&mut buffer, &mut literals);
+            }
+        }
+        i += run;
+    }
+    flush(&mut buffer, &mut literals);
+    buffer
+}
+
+/// Reverses [compress]. Returns `None` if the data is cut off or would decompress to more than `max_len` bytes.
+fn decompress(data: &[u8], max_len: usize) -> Option<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let header = data[i] as usize;
+        i += 1;
+        if header <= 127 {
+            if buffer.len() + header + 1 > max_len {
+                return None;
+            }
+            buffer.extend_from_slice(data.get(i..i + header + 1)?);
+            i += header + 1;
+        } else if header > 128 {
+            if buffer.len() + 257 - header > max_len {
+                return None;
+            }
+            let byte = *data.get(i)?;
+            buffer.extend(std::iter::repeat_n(byte, 257 - header));
+            i += 1;
+        }
+    }
+    Some(buffer)
+}
+
+fn wrap(magic: [u8; 4], version: u16, payload: Vec<u8>) -> Vec<u8> {
+    let compressed = compress(&payload);
+    let (version, payload) = if compressed.len() < payload.len() {
+        (version | COMPRESSED_FLAG, compressed)
+    } else {
+        (version, payload)
+    };
+    let mut buffer = Vec::with_capacity(payload.len() + 6);
+    buffer.extend_from_slice(&magic);
+    buffer.extend_from_slice(&version.to_le_bytes());
+    buffer.extend(payload);
+    buffer
+}
+
+/// Splits a buffer into its format version and (decompressed) payload.
+///
+/// Payloads larger than `max_len` are rejected as corrupt, so broken or crafted data can't make it
+/// allocate without limit.
+fn unwrap(
+    magic: [u8; 4],
+    buffer: &[u8],
+    max_len: usize,
+) -> Result<(u16, Cow<'_, [u8]>), FormatError> {
+    if buffer.len() >= 6 && buffer[..4] == magic {
+        let version = u16::from_le_bytes([buffer[4], buffer[5]]);
+        let payload = &buffer[6..];
+        if version & COMPRESSED_FLAG != 0 {
+            let payload = decompress(payload, max_len).ok_or(FormatError::Corrupt)?;
+            Ok((version & !COMPRESSED_FLAG, Cow::Owned(payload)))
+        } else if payload.len() > max_len {
+            Err(FormatError::Corrupt)
+        } else {
+            Ok((version, Cow::Borrowed(payload)))
+        }
+    } else if buffer.len() >= 8
+        && buffer.len() <= max_len
+        && legacy_length(buffer) <= buffer.len() - 8
+    {
+        Ok((0, Cow::Borrowed(buffer)))
+    } else {
+        Err(FormatError::Corrupt)
+    }
+}
+
+/// Both legacy layouts start with the length of a list, and every item takes up at least a byte.
+/// Checking it against the size of the payload catches corrupted headers, which would otherwise
+/// make nanoserde try to allocate a huge list.
+fn legacy_length(buffer: &[u8]) -> usize {
+    let length = u64::from_le_bytes(buffer[..8].try_into().unwrap());
+    usize::try_from(length).unwrap_or(usize::MAX)
+}
+
+/// [Character] as it was up to level version 1, before enemies had a starting direction.
+#[derive(DeBin, DeRon, SerRon)]
+#[cfg_attr(test, derive(SerBin))]
+enum CharacterV1 {
+    PlayerSpawn,
+    Flag,
+    WanderEnemy(usize),
+}
+impl From<CharacterV1> for Character {
+    fn from(value: CharacterV1) -> Self {
+        match value {
+            CharacterV1::PlayerSpawn => Character::PlayerSpawn,
+            CharacterV1::Flag => Character::Flag,
+            CharacterV1::WanderEnemy(animation) => Character::wander_enemy(animation),
+        }
+    }
+}
+
+/// [Character] as it was in level version 2, before enemies had per-instance properties.
+#[derive(DeBin)]
+enum CharacterV2 {
+    PlayerSpawn,
+    Flag,
+    WanderEnemy { animation: usize, moving_left: bool },
+}
+impl From<CharacterV2> for Character {
+    fn from(value: CharacterV2) -> Self {
+        match value {
+            CharacterV2::PlayerSpawn => Character::PlayerSpawn,
+            CharacterV2::Flag => Character::Flag,
+            CharacterV2::WanderEnemy {
+                animation,
+                moving_left,
+            } => Character::WanderEnemy {
+                animation,
+                moving_left,
+                speed: DEFAULT_ENEMY_SPEED,
+                patrol_range: 0,
+                turn_at_ledges: false,
+            },
+        }
+    }
+}
+
+/// [Level] as it was up to level version 1.
+#[derive(DeBin)]
+#[cfg_attr(test, derive(SerBin))]
+struct LevelV1 {
+    tiles: Vec<[u8; 2]>,
+    width: usize,
+    characters: Vec<((f32, f32), CharacterV1, usize)>,
+}
+impl From<LevelV1> for Level {
+    fn from(value: LevelV1) -> Self {
+        Level {
+            tiles: value.tiles,
+            width: value.width,
+            characters: value
+                .characters
+                .into_iter()
+                .map(|(pos, character, tile)| (pos, character.into(), tile))
+                .collect(),
+        }
+    }
+}
+
+/// [Level] as it was in level version 2.
+#[derive(DeBin)]
+struct LevelV2 {
+    tiles: Vec<[u8; 2]>,
+    width: usize,
+    characters: Vec<((f32, f32), CharacterV2, usize)>,
+}
+impl From<LevelV2> for Level {
+    fn from(value: LevelV2) -> Self {
+        Level {
+            tiles: value.tiles,
+            width: value.width,
+            characters: value
+                .characters
+                .into_iter()
+                .map(|(pos, character, tile)| (pos, character.into(), tile))
+                .collect(),
+        }
+    }
+}
+
+fn migrate_level(version: u16, payload: &[u8]) -> Result<Level, FormatError> {
+    match version {
+        0 | 1 => LevelV1::de_bin(&mut 0, payload)
+            .map(Level::from)
+            .map_err(|_| FormatError::Corrupt),
+        2 => LevelV2::de_bin(&mut 0, payload)
+            .map(Level::from)
+            .map_err(|_| FormatError::Corrupt),
+        3 => Level::de_bin(&mut 0, payload).map_err(|_| FormatError::Corrupt),
+        _ => Err(FormatError::TooNew(version)),
+    }
+}
+
+fn upgrade_levels<T: Into<Level>>(levels: Vec<(String, T)>) -> Vec<(String, Level)> {
+    levels
+        .into_iter()
+        .map(|(name, level)| (name, level.into()))
+        .collect()
+}
+
+/// [LocalData] as it was up to save version 1, before stamps existed.
+#[derive(DeBin)]
+#[cfg_attr(test, derive(SerBin))]
+struct LocalDataV1 {
+    user_levels: Vec<(String, LevelV1)>,
+    completed_online_levels: Vec<String>,
+}
+
+/// [LocalData] as it was in save version 2, with levels from before level version 2.
+#[derive(DeBin)]
+struct LocalDataV2 {
+    user_levels: Vec<(String, LevelV1)>,
+    completed_online_levels: Vec<String>,
+    stamps: Vec<(String, LevelV1)>,
+}
+
+/// [LocalData] as it was in save version 3, before the autosave recovery slot.
+#[derive(DeBin)]
+struct LocalDataV3 {
+    user_levels: Vec<(String, LevelV2)>,
+    completed_online_levels: Vec<String>,
+    stamps: Vec<(String, LevelV2)>,
+}
+
+/// [LocalData] as it was in save version 4, before level snapshots.
+#[derive(DeBin)]
+struct LocalDataV4 {
+    user_levels: Vec<(String, LevelV2)>,
+    completed_online_levels: Vec<String>,
+    stamps: Vec<(String, LevelV2)>,
+    recovery: Option<(Option<String>, LevelV2)>,
+}
+
+/// [LocalData] as it was in save version 5, with levels from before level version 3.
+#[derive(DeBin)]
+struct LocalDataV5 {
+    user_levels: Vec<(String, LevelV2)>,
+    completed_online_levels: Vec<String>,
+    stamps: Vec<(String, LevelV2)>,
+    recovery: Option<(Option<String>, LevelV2)>,
+    snapshots: Vec<(String, Vec<(u64, LevelV2)>)>,
+}
+
+/// [LocalData] as it was in save version 6, with the recovery slot and snapshots stored along with it.
+#[derive(DeBin)]
+#[cfg_attr(test, derive(SerBin))]
+struct LocalDataV6 {
+    user_levels: Vec<(String, Level)>,
+    completed_online_levels: Vec<String>,
+    stamps: Vec<(String, Level)>,
+    recovery: Option<Recovery>,
+    snapshots: Snapshots,
+}
+
+/// Parts of the save data that were stored along with [LocalData] in save versions 4 to 6,
+/// and are stored on their own now.
+#[derive(Default)]
+pub struct MovedData {
+    pub recovery: Option<Recovery>,
+    pub snapshots: Snapshots,
+}
+
+fn upgrade_recovery(
+    recovery: Option<(Option<String>, LevelV2)>,
+) -> Option<(Option<String>, Level)> {
+    recovery.map(|(name, level)| (name, level.into()))
+}
+
+fn migrate_local_data(version: u16, payload: &[u8]) -> Result<(LocalData, MovedData), FormatError> {
+    match version {
+        0 | 1 => {
+            let old = LocalDataV1::de_bin(&mut 0, payload).map_err(|_| FormatError::Corrupt)?;
+            let data = LocalData {
+                user_levels: upgrade_levels(old.user_levels),
+                completed_online_levels: old.completed_online_levels,
+                stamps: Vec::new(),
+            };
+            Ok((data, MovedData::default()))
+        }
+        2 => {
+            let old = LocalDataV2::de_bin(&mut 0, payload).map_err(|_| FormatError::Corrupt)?;
+            let data = LocalData {
+                user_levels: upgrade_levels(old.user_levels),
+                completed_online_levels: old.completed_online_levels,
+                stamps: upgrade_levels(old.stamps),
+            };
+            Ok((data, MovedData::default()))
+        }
+        3 => {
+            let old = LocalDataV3::de_bin(&mut 0, payload).map_err(|_| FormatError::Corrupt)?;
+            let data = LocalData {
+                user_levels: upgrade_levels(old.user_levels),
+                completed_online_levels: old.completed_online_levels,
+                stamps: upgrade_levels(old.stamps),
+            };
+            Ok((data, MovedData::default()))
+        }
+        4 => {
+            let old = LocalDataV4::de_bin(&mut 0, payload).map_err(|_| FormatError::Corrupt)?;
+            let data = LocalData {
+                user_levels: upgrade_levels(old.user_levels),
+                completed_online_levels: old.completed_online_levels,
+                stamps: upgrade_levels(old.stamps),
+            };
+            let moved = MovedData {
+                recovery: upgrade_recovery(old.recovery),
+                snapshots: Vec::new(),
+            };
+            Ok((data, moved))
+        }
+        5 => {
+            let old = LocalDataV5::de_bin(&mut 0, payload).map_err(|_| FormatError::Corrupt)?;
+            let data = LocalData {
+                user_levels: upgrade_levels(old.user_levels),
+                completed_online_levels: old.completed_online_levels,
+                stamps: upgrade_levels(old.stamps),
+            };
+            let moved = MovedData {
+                recovery: upgrade_recovery(old.recovery),
+                snapshots: old
+                    .snapshots
+                    .into_iter()
+                    .map(|(name, snapshots)| {
+                        let snapshots = snapshots
+                            .into_iter()
+                            .map(|(timestamp, level)| (timestamp, level.into()))
+                            .collect();
+                        (name, snapshots)
+                    })
+                    .collect(),
+            };
+            Ok((data, moved))
+        }
+        6 => {
+            let old = LocalDataV6::de_bin(&mut 0, payload).map_err(|_| FormatError::Corrupt)?;
+            let data = LocalData {
+                user_levels: old.user_levels,
+                completed_online_levels: old.completed_online_levels,
+                stamps: old.stamps,
+            };
+            let moved = MovedData {
+                recovery: old.recovery,
+                snapshots: old.snapshots,
+            };
+            Ok((data, moved))
+        }
+        7 => {
+            let data = LocalData::de_bin(&mut 0, payload).map_err(|_| FormatError::Corrupt)?;
+            Ok((data, MovedData::default()))
+        }
+        _ => Err(FormatError::TooNew(version)),
+    }
+}
+
+pub fn encode_level(level: &Level) -> Vec<u8> {
+    wrap(LEVEL_MAGIC, LEVEL_VERSION, level.serialize_bin())
+}
+
+pub fn decode_level(buffer: &[u8]) -> Result<Level, FormatError> {
+    let (version, payload) = unwrap(LEVEL_MAGIC, buffer, MAX_LEVEL_PAYLOAD)?;
+    migrate_level(version, &payload)
+}
+
+pub fn encode_local_data(data: &LocalData) -> Vec<u8> {
+    wrap(SAVE_MAGIC, SAVE_VERSION, data.serialize_bin())
+}
+
+/// Decodes save data, along with the parts of it that older save versions stored in the same file
+pub fn decode_local_data(buffer: &[u8]) -> Result<(LocalData, MovedData), FormatError> {
+    let (version, payload) = unwrap(SAVE_MAGIC, buffer, MAX_SAVE_PAYLOAD)?;
+    migrate_local_data(version, &payload)
+}
+
+pub fn encode_recovery(recovery: &Recovery) -> Vec<u8> {
+    wrap(RECOVERY_MAGIC, RECOVERY_VERSION, recovery.serialize_bin())
+}
+
+pub fn decode_recovery(buffer: &[u8]) -> Result<Recovery, FormatError> {
+    let max_len = MAX_LEVEL_PAYLOAD + MAX_LEVEL_NAME_LENGTH * 4 + 16;
+    match unwrap(RECOVERY_MAGIC, buffer, max_len)? {
+        (1, payload) => Recovery::de_bin(&mut 0, &payload).map_err(|_| FormatError::Corrupt),
+        (0, _) => Err(FormatError::Corrupt),
+        (version, _) => Err(FormatError::TooNew(version)),
+    }
+}
+
+pub fn encode_snapshots(snapshots: &Snapshots) -> Vec<u8> {
+    wrap(
+        SNAPSHOTS_MAGIC,
+        SNAPSHOTS_VERSION,
+        snapshots.serialize_bin(),
+    )
+}
+
+pub fn decode_snapshots(buffer: &[u8]) -> Result<Snapshots, FormatError> {
+    match unwrap(SNAPSHOTS_MAGIC, buffer, MAX_SNAPSHOTS_PAYLOAD)? {
+        (1, payload) => Snapshots::de_bin(&mut 0, &payload).map_err(|_| FormatError::Corrupt),
+        (0, _) => Err(FormatError::Corrupt),
+        (version, _) => Err(FormatError::TooNew(version)),
+    }
+}
+
+#[derive(SerRon, DeRon)]
+struct TextCharacter {
+    kind: CharacterV1,
+    x: f32,
+    y: f32,
+    sprite: usize,
+    /// Only used by enemies. Missing in files from before level version 2.
+    #[nserde(default)]
+    moving_right: bool,
+    /// Only used by enemies, like the fields below. Missing in files from before level version 3.
+    #[nserde(default_with = "default_enemy_speed")]
+    speed: f32,
+    #[nserde(default)]
+    patrol_range: u8,
+    #[nserde(default)]
+    turn_at_ledges: bool,
+}
+fn default_enemy_speed() -> f32 {
+    DEFAULT_ENEMY_SPEED
+}
+
+/// Text representation of a [Level]. Each tile layer is stored as one string per row,
+/// with the tile ids separated by spaces.
+#[derive(SerRon, DeRon)]
+struct TextLevel {
+    version: u16,
+    width: usize,
+    terrain: Vec<String>,
+    obstacles: Vec<String>,
+    characters: Vec<TextCharacter>,
+}
+
+pub fn level_to_text(level: &Level) -> String {
+    let layer = |index: usize| {
+        level
+            .tiles
+            .chunks(level.width.max(1))
+            .map(|row| {
+                row.iter()
+                    .map(|f| f[index].to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            })
+            .collect()
+    };
+    TextLevel {
+        version: LEVEL_VERSION,
+        width: level.width,
+        terrain: layer(0),
+        obstacles: layer(1),
+        characters: level
+            .characters
+            .iter()
+            .map(|(pos, kind, sprite)| {
+                let mut character = TextCharacter {
+                    kind: CharacterV1::PlayerSpawn,
+                    x: pos.0,
+                    y: pos.1,
+                    sprite: *sprite,
+                    moving_right: false,
+                    speed: DEFAULT_ENEMY_SPEED,
+                    patrol_range: 0,
+                    turn_at_ledges: false,
+                };
+                match kind {
+                    Character::PlayerSpawn => {}
+                    Character::Flag => character.kind = CharacterV1::Flag,
+                    Character::WanderEnemy {
+                        animation,
+                        moving_left,
+                        speed,
+                        patrol_range,
+                        turn_at_ledges,
+                    } => {
+                        character.kind = CharacterV1::WanderEnemy(*animation);
+                        character.moving_right = !*moving_left;
+                        character.speed = *speed;
+                        character.patrol_range = *patrol_range;
+                        character.turn_at_ledges = *turn_at_ledges;
+                    }
+                }
+                character
+            })
+            .collect(),
+    }
+    .serialize_ron()
+}
+
+pub fn level_from_text(text: &str) -> Result<Level, FormatError> {
+    let parsed = TextLevel::deserialize_ron(text)
+        .map_err(|e| FormatError::Malformed(format!("{:?}on line {}", e.msg, e.line + 1)))?;
+    if parsed.version > LEVEL_VERSION {
+        return Err(FormatError::TooNew(parsed.version));
+    }
+    if parsed.terrain.len() != parsed.obstacles.len() {
+        return Err(FormatError::Malformed(
+            "terrain and obstacles differ in height".to_string(),
+        ));
+    }
+
+    let mut tiles = Vec::with_capacity(parsed.width * parsed.terrain.len());
+    for (y, (terrain, obstacles)) in parsed.terrain.iter().zip(&parsed.obstacles).enumerate() {
+        let parse_row = |row: &str| -> Result<Vec<u8>, FormatError> {
+            let row: Vec<u8> = row
+                .split_whitespace()
+                .map(|f| f.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| FormatError::Malformed(format!("bad tile id on row {y}")))?;
+            if row.len() != parsed.width {
+                return Err(FormatError::Malformed(format!("row {y} has wrong width")));
+            }
+            Ok(row)
+        };
+        let terrain = parse_row(terrain)?;
+        let obstacles = parse_row(obstacles)?;
+        tiles.extend(terrain.into_iter().zip(obstacles).map(|(a, b)| [a, b]));
+    }
+
+    Ok(Level {
+        tiles,
+        width: parsed.width,
+        characters: parsed
+            .characters
+            .into_iter()
+            .map(|f| {
+                let mut kind = Character::from(f.kind);
+                if let Character::WanderEnemy {
+                    moving_left,
+                    speed,
+                    patrol_range,
+                    turn_at_ledges,
+                    ..
+                } = &mut kind
+                {
+                    *moving_left = !f.moving_right;
+                    *speed = f.speed;
+                    *patrol_range = f.patrol_range;
+                    *turn_at_ledges = f.turn_at_ledges;
+                }
+                ((f.x, f.y), kind, f.sprite)
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::test_levels::test_level;
+
+    fn legacy_level() -> LevelV1 {
+        LevelV1 {
+            tiles: vec![[1, 0]; 16],
+            width: 4,
+            characters: vec![
+                ((0.0, 0.0), CharacterV1::PlayerSpawn, 0),
+                ((16.0, 0.0), CharacterV1::Flag, 1),
+                ((32.0, 0.0), CharacterV1::WanderEnemy(1), 2),
+            ],
+        }
+    }
+
+    #[test]
+    fn level_round_trip() {
+        let level = test_level();
+        let encoded = encode_level(&level);
+        assert_eq!(encoded[..4], LEVEL_MAGIC);
+        assert!(decode_level(&encoded).is_ok_and(|f| f == level));
+    }
+
+    #[test]
+    fn version_is_stored_in_header() {
+        let encoded = encode_level(&test_level());
+        let version = u16::from_le_bytes([encoded[4], encoded[5]]);
+        assert_eq!(version & !COMPRESSED_FLAG, LEVEL_VERSION);
+    }
+
+    #[test]
+    fn local_data_round_trip() {
+        let data = LocalData {
+            user_levels: vec![("level".to_string(), test_level())],
+            completed_online_levels: vec!["online".to_string()],
+            stamps: vec![("stamp".to_string(), test_level())],
+        };
+        let encoded = encode_local_data(&data);
+        assert_eq!(encoded[..4], SAVE_MAGIC);
+        let (decoded, moved) = decode_local_data(&encoded).ok().unwrap();
+        assert!(decoded.user_levels == data.user_levels);
+        assert!(decoded.completed_online_levels == data.completed_online_levels);
+        assert!(decoded.stamps == data.stamps);
+        assert!(moved.recovery.is_none());
+        assert!(moved.snapshots.is_empty());
+    }
+
+    #[test]
+    fn recovery_and_snapshots_round_trip() {
+        let recovery = (Some("level".to_string()), test_level());
+        let encoded = encode_recovery(&recovery);
+        assert_eq!(encoded[..4], RECOVERY_MAGIC);
+        assert!(decode_recovery(&encoded).is_ok_and(|f| f == recovery));
+        let snapshots = vec![("level".to_string(), vec![(1, test_level())])];
+        let encoded = encode_snapshots(&snapshots);
+        assert_eq!(encoded[..4], SNAPSHOTS_MAGIC);
+        assert!(decode_snapshots(&encoded).is_ok_and(|f| f == snapshots));
+        // every kind of data has its own magic bytes
+        assert!(decode_recovery(&encode_local_data(&LocalData::default())).is_err());
+    }
+
+    #[test]
+    fn splits_recovery_and_snapshots_from_save_version_6() {
+        let data = LocalDataV6 {
+            user_levels: vec![("level".to_string(), test_level())],
+            completed_online_levels: Vec::new(),
+            stamps: Vec::new(),
+            recovery: Some((None, test_level())),
+            snapshots: vec![("level".to_string(), vec![(1, test_level())])],
+        };
+        let encoded = wrap(SAVE_MAGIC, 6, data.serialize_bin());
+        let (decoded, moved) = decode_local_data(&encoded).ok().unwrap();
+        assert!(decoded.user_levels == data.user_levels);
+        assert!(moved.recovery == data.recovery);
+        assert!(moved.snapshots == data.snapshots);
+    }
+
+    #[test]
+    fn migrates_legacy_level() {
+        // levels from before the envelope are the bare payload
+        let level = decode_level(&legacy_level().serialize_bin()).ok().unwrap();
+        assert_eq!(level.width, 4);
+        assert!(level.tiles == vec![[1, 0]; 16]);
+        assert!(matches!(level.characters[0].1, Character::PlayerSpawn));
+        assert!(matches!(level.characters[1].1, Character::Flag));
+        assert!(level.characters[2].1 == Character::wander_enemy(1));
+    }
+
+    #[test]
+    fn migrates_legacy_local_data() {
+        let data = LocalDataV1 {
+            user_levels: vec![("old".to_string(), legacy_level())],
+            completed_online_levels: vec!["online".to_string()],
+        };
+        let (data, moved) = decode_local_data(&data.serialize_bin()).ok().unwrap();
+        assert_eq!(data.user_levels.len(), 1);
+        assert_eq!(data.user_levels[0].0, "old");
+        assert_eq!(data.completed_online_levels, ["online"]);
+        assert!(data.stamps.is_empty());
+        assert!(moved.recovery.is_none());
+        assert!(moved.snapshots.is_empty());
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let mut encoded = encode_level(&test_level());
+        let flags = u16::from_le_bytes([encoded[4], encoded[5]]) & COMPRESSED_FLAG;
+        encoded[4..6].copy_from_slice(&((LEVEL_VERSION + 1) | flags).to_le_bytes());
+        assert!(matches!(
+            decode_level(&encoded),
+            Err(FormatError::TooNew(version)) if version == LEVEL_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupted_header() {
+        let mut encoded = encode_level(&test_level());
+        encoded[3] = b'X';
+        assert!(matches!(decode_level(&encoded), Err(FormatError::Corrupt)));
+        let mut encoded = encode_local_data(&LocalData::default());
+        encoded[0] = 0xff;
+        assert!(matches!(
+            decode_local_data(&encoded),
+            Err(FormatError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_payload() {
+        let encoded = encode_level(&test_level());
+        for length in [0, 3, 6, encoded.len() - 1] {
+            assert!(decode_level(&encoded[..length]).is_err());
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let mut level = test_level();
+        level.characters.push((
+            (64.0, 32.0),
+            Character::WanderEnemy {
+                animation: 1,
+                moving_left: false,
+                speed: 12.5,
+                patrol_range: 3,
+                turn_at_ledges: true,
+            },
+            2,
+        ));
+        let text = level_to_text(&level);
+        assert!(level_from_text(&text).is_ok_and(|f| f == level));
+        // and the text itself is stable
+        assert_eq!(level_to_text(&level_from_text(&text).ok().unwrap()), text);
+    }
+
+    #[test]
+    fn empty_level_text_round_trip() {
+        for width in [0, 8] {
+            let level = Level {
+                tiles: Vec::new(),
+                width,
+                characters: Vec::new(),
+            };
+            assert!(level_from_text(&level_to_text(&level)).is_ok_and(|f| f == level));
+        }
+    }
+
+    #[test]
+    fn text_defaults_missing_enemy_properties() {
+        let text = "(version: 1, width: 1, terrain: [\"0\"], obstacles: [\"0\"], characters: [
+            (kind: WanderEnemy(0), x: 0.0, y: 0.0, sprite: 2),
+        ])";
+        let level = level_from_text(text).ok().unwrap();
+        assert!(level.characters[0].1 == Character::wander_enemy(0));
+    }
+
+    #[test]
+    fn rejects_malformed_text() {
+        let text = |version: u16, terrain: &str| {
+            format!(
+                "(version: {version}, width: 2, terrain: [\"{terrain}\"], obstacles: [\"0 0\"], characters: [])"
+            )
+        };
+        assert!(level_from_text(&text(LEVEL_VERSION, "0 0")).is_ok());
+        assert!(matches!(
+            level_from_text(&text(LEVEL_VERSION, "0")),
+            Err(FormatError::Malformed(_))
+        ));
+        assert!(matches!(
+            level_from_text(&text(LEVEL_VERSION, "0 x")),
+            Err(FormatError::Malformed(_))
+        ));
+        assert!(matches!(
+            level_from_text(&text(LEVEL_VERSION + 1, "0 0")),
+            Err(FormatError::TooNew(_))
+        ));
+        assert!(matches!(
+            level_from_text("(width: 2)"),
+            Err(FormatError::Malformed(_))
+        ));
+    }
+
+    fn assert_packbits_round_trip(data: &[u8]) {
+        assert_eq!(decompress(&compress(data), usize::MAX).unwrap(), data);
+    }
+
+    #[test]
+    fn packbits_round_trip() {
+        assert_packbits_round_trip(&[]);
+        assert_packbits_round_trip(&[7]);
+        assert_packbits_round_trip(&[1, 2, 2, 3, 3, 3, 4]);
+        let mixed: Vec<u8> = (0..1000).map(|f: u32| (f / 7 % 3) as u8).collect();
+        assert_packbits_round_trip(&mixed);
+    }
+
+    #[test]
+    fn packbits_run_boundaries() {
+        // runs are at most 128 long, so longer ones get split
+        for length in [2, 127, 128, 129, 130, 256, 257] {
+            let data = vec![9; length];
+            assert_packbits_round_trip(&data);
+        }
+        assert_eq!(compress(&[9; 128]), [129, 9]);
+        assert_eq!(compress(&[9; 129]), [129, 9, 0, 9]);
+        assert_eq!(compress(&[9; 130]), [129, 9, 255, 9]);
+    }
+
+    #[test]
+    fn packbits_literal_boundaries() {
+        // literal chunks are at most 128 long too
+        for length in [1, 127, 128, 129, 256, 300] {
+            let data: Vec<u8> = (0..length).map(|f| f as u8).collect();
+            assert_packbits_round_trip(&data);
+        }
+        let data: Vec<u8> = (0..=128).collect();
+        let compressed = compress(&data);
+        assert_eq!(compressed[0], 127);
+        assert_eq!(compressed[129..], [0, 128]);
+    }
+
+    #[test]
+    fn packbits_rejects_truncated_data() {
+        // literal chunk missing its last byte
+        assert!(decompress(&[2, 1, 2], usize::MAX).is_none());
+        // run missing the byte to repeat
+        assert!(decompress(&[200], usize::MAX).is_none());
+        // 128 is a no-op header
+        assert_eq!(decompress(&[128, 0, 5], usize::MAX).unwrap(), [5]);
+    }
+
+    #[test]
+    fn packbits_output_is_capped() {
+        assert_eq!(decompress(&[129, 9, 0, 9], 129).unwrap().len(), 129);
+        assert!(decompress(&[129, 9, 0, 9], 128).is_none());
+        assert!(decompress(&[3, 1, 2, 3, 4], 3).is_none());
+
+        // a small envelope that would decompress far past the largest level
+        let mut bomb = LEVEL_MAGIC.to_vec();
+        bomb.extend_from_slice(&(LEVEL_VERSION | COMPRESSED_FLAG).to_le_bytes());
+        for _ in 0..MAX_LEVEL_PAYLOAD / 128 + 1 {
+            bomb.extend_from_slice(&[129, 0]);
+        }
+        assert!(matches!(decode_level(&bomb), Err(FormatError::Corrupt)));
+    }
+
+    #[test]
+    fn only_compresses_when_smaller() {
+        let payload: Vec<u8> = (0..64).collect();
+        let wrapped = wrap(LEVEL_MAGIC, 2, payload.clone());
+        assert_eq!(wrapped[4..6], 2u16.to_le_bytes());
+        assert_eq!(wrapped[6..], payload);
+        let (version, unwrapped) = unwrap(LEVEL_MAGIC, &wrapped, usize::MAX).ok().unwrap();
+        assert_eq!((version, &unwrapped[..]), (2, &payload[..]));
+    }
+
+    #[test]
+    fn max_size_level_round_trip() {
+        let mut tiles = vec![[0, 0]; MAX_LEVEL_SIZE * MAX_LEVEL_SIZE];
+        tiles[MAX_LEVEL_SIZE * (MAX_LEVEL_SIZE - 1)..].fill([1, 0]);
+        tiles[12345] = [4, 2];
+        let level = Level {
+            tiles,
+            width: MAX_LEVEL_SIZE,
+            characters: vec![
+                ((0.0, 0.0), Character::PlayerSpawn, 0),
+                ((16368.0, 16368.0), Character::Flag, 1),
+            ],
+        };
+        let encoded = encode_level(&level);
+        assert_ne!(
+            u16::from_le_bytes([encoded[4], encoded[5]]) & COMPRESSED_FLAG,
+            0
+        );
+        // a mostly empty level shrinks to a few percent of its size
+        assert!(encoded.len() < level.tiles.len() * 2 / 50);
+        assert!(decode_level(&encoded).is_ok_and(|f| f == level));
+    }
+}
@@ -0,0 +1,304 @@
+//! Mod for the undo/redo history of the level editor
+
+use crate::level::{Character, Level, LevelRenderer};
+
+/// Keeps a drawn view of the level up to date as the history changes it.
+///
+/// Implemented by [LevelRenderer], and kept as a trait so the history also works without one.
+pub trait Redraw {
+    /// Called after tiles were changed directly in the level
+    fn redraw_tiles(&mut self, level: &Level, positions: &[(usize, usize)]);
+    /// Called after the whole level was replaced
+    fn rebuild(&mut self, level: &Level);
+}
+impl Redraw for LevelRenderer<'_> {
+    fn redraw_tiles(&mut self, level: &Level, positions: &[(usize, usize)]) {
+        LevelRenderer::redraw_tiles(self, level, positions);
+    }
+    fn rebuild(&mut self, level: &Level) {
+        LevelRenderer::rebuild(self, level);
+    }
+}
+
+/// How many steps are kept in the undo history at most
+const MAX_HISTORY: usize = 256;
+
+type CharacterList = Vec<((f32, f32), Character, usize)>;
+
+enum Edit {
+    Tile {
+        x: usize,
+        y: usize,
+        old: [u8; 2],
+        new: [u8; 2],
+    },
+    Characters {
+        old: CharacterList,
+        new: CharacterList,
+    },
+    /// The whole level was replaced, such as when resizing
+    Level { old: Level, new: Level },
+}
+
+/// Tracks edits made to a level, grouped into steps that can be undone and redone.
+///
+/// All edits should go through this, and then be grouped into a single step with [History::commit]
+/// once the action (such as one drag stroke) is done.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<(usize, Vec<Edit>)>,
+    redo: Vec<(usize, Vec<Edit>)>,
+    pending: Vec<Edit>,
+    pending_characters: Option<CharacterList>,
+    /// Id of the state before the oldest step in the undo stack
+    base: usize,
+    next_id: usize,
+}
+impl History {
+    pub fn set_tile(
+        &mut self,
+        level: &mut Level,
+        renderer: &mut impl Redraw,
+        x: usize,
+        y: usize,
+        tile: [u8; 2],
+    ) {
+        let old = level.get_tile(x, y);
+        if old != tile {
+            self.pending.push(Edit::Tile {
+                x,
+                y,
+                old,
+                new: tile,
+            });
+            level.tiles[x + y * level.width] = tile;
+            renderer.redraw_tiles(level, &[(x, y)]);
+        }
+    }
+    /// Sets many tiles at once, redrawing them in one go
+    pub fn set_tiles(
+        &mut self,
+        level: &mut Level,
+        renderer: &mut impl Redraw,
+        changes: Vec<(usize, usize, [u8; 2])>,
+    ) {
+        let mut positions = Vec::with_capacity(changes.len());
+        for (x, y, tile) in changes {
+            let old = level.get_tile(x, y);
+            if old != tile {
+                self.pending.push(Edit::Tile {
+                    x,
+                    y,
+                    old,
+                    new: tile,
+                });
+                level.tiles[x + y * level.width] = tile;
+                positions.push((x, y));
+            }
+        }
+        renderer.redraw_tiles(level, &positions);
+    }
+    /// Must be called before modifying the characters of the level, so they can be restored
+    pub fn modify_characters(&mut self, level: &Level) {
+        if self.pending_characters.is_none() {
+            self.pending_characters = Some(level.characters.clone());
+        }
+    }
+    /// Replaces the entire level, rebuilding the renderer
+    pub fn replace_level(&mut self, level: &mut Level, renderer: &mut impl Redraw, new: Level) {
+        let old = std::mem::replace(level, new.clone());
+        renderer.rebuild(level);
+        self.pending.push(Edit::Level { old, new });
+    }
+    /// Groups all edits since the last commit into one step. Returns true if anything changed.
+    pub fn commit(&mut self, level: &Level) -> bool {
+        if let Some(old) = self.pending_characters.take()
+            && old != level.characters
+        {
+            self.pending.push(Edit::Characters {
+                old,
+                new: level.characters.clone(),
+            });
+        }
+        if self.pending.is_empty() {
+            return false;
+        }
+        self.next_id += 1;
+        self.undo
+            .push((self.next_id, std::mem::take(&mut self.pending)));
+        self.redo.clear();
+        if self.undo.len() > MAX_HISTORY {
+            self.base = self.undo.remove(0).0;
+        }
+        true
+    }
+    /// Id of the current state of the level, for comparing with earlier states
+    pub fn state(&self) -> usize {
+        self.undo.last().map(|f| f.0).unwrap_or(self.base)
+    }
+    fn apply(edits: &[Edit], undo: bool, level: &mut Level, renderer: &mut impl Redraw) {
+        // changed tiles are redrawn all at once at the end
+        let mut positions = Vec::new();
+        let mut apply_edit = |edit: &Edit| match edit {
+            Edit::Tile { x, y, old, new } => {
+                level.tiles[x + y * level.width] = if undo { *old } else { *new };
+                positions.push((*x, *y));
+            }
+            Edit::Characters { old, new } => {
+                level.characters = if undo { old.clone() } else { new.clone() };
+            }
+            Edit::Level { old, new } => {
+                *level = if undo { old.clone() } else { new.clone() };
+                renderer.rebuild(level);
+                positions.clear();
+            }
+        };
+        if undo {
+            edits.iter().rev().for_each(&mut apply_edit);
+        } else {
+            edits.iter().for_each(&mut apply_edit);
+        }
+        renderer.redraw_tiles(level, &positions);
+    }
+    /// Undoes the last step. Returns true if there was anything to undo.
+    pub fn undo(&mut self, level: &mut Level, renderer: &mut impl Redraw) -> bool {
+        let Some(step) = self.undo.pop() else {
+            return false;
+        };
+        Self::apply(&step.1, true, level, renderer);
+        self.redo.push(step);
+        true
+    }
+    /// Redoes the last undone step. Returns true if there was anything to redo.
+    pub fn redo(&mut self, level: &mut Level, renderer: &mut impl Redraw) -> bool {
+        let Some(step) = self.redo.pop() else {
+            return false;
+        };
+        Self::apply(&step.1, false, level, renderer);
+        self.undo.push(step);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::test_levels::empty_level;
+
+    /// Counts how the history asked for the level to be redrawn
+    #[derive(Default)]
+    struct Counter {
+        redrawn_tiles: usize,
+        rebuilds: usize,
+    }
+    impl Redraw for Counter {
+        fn redraw_tiles(&mut self, _: &Level, positions: &[(usize, usize)]) {
+            self.redrawn_tiles += positions.len();
+        }
+        fn rebuild(&mut self, _: &Level) {
+            self.rebuilds += 1;
+        }
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut level = empty_level(4, 4);
+        let mut counter = Counter::default();
+        let mut history = History::default();
+        let original = level.clone();
+
+        history.set_tile(&mut level, &mut counter, 1, 1, [1, 0]);
+        history.set_tiles(
+            &mut level,
+            &mut counter,
+            vec![(2, 1, [1, 0]), (3, 1, [0, 2])],
+        );
+        history.modify_characters(&level);
+        level.characters[0].0 = (16.0, 0.0);
+        assert!(history.commit(&level));
+        let edited = level.clone();
+        assert_eq!(counter.redrawn_tiles, 3);
+
+        assert!(history.undo(&mut level, &mut counter));
+        assert!(level == original);
+        assert!(!history.undo(&mut level, &mut counter));
+        assert!(history.redo(&mut level, &mut counter));
+        assert!(level == edited);
+        assert!(!history.redo(&mut level, &mut counter));
+    }
+
+    #[test]
+    fn empty_steps_are_not_committed() {
+        let mut level = empty_level(4, 4);
+        let mut history = History::default();
+        history.set_tile(&mut level, &mut Counter::default(), 0, 0, [0, 0]);
+        history.modify_characters(&level);
+        assert!(!history.commit(&level));
+        assert_eq!(history.state(), 0);
+    }
+
+    #[test]
+    fn replace_level() {
+        let mut level = empty_level(4, 4);
+        let mut counter = Counter::default();
+        let mut history = History::default();
+        let mut resized = empty_level(4, 4);
+        resized.resize(8, 2, (0, 0));
+        history.replace_level(&mut level, &mut counter, resized.clone());
+        history.commit(&level);
+        assert!(history.undo(&mut level, &mut counter));
+        assert!(level == empty_level(4, 4));
+        assert!(history.redo(&mut level, &mut counter));
+        assert!(level == resized);
+        assert_eq!(counter.rebuilds, 3);
+    }
+
+    #[test]
+    fn undo_past_saved_state() {
+        let mut level = empty_level(4, 4);
+        let mut counter = Counter::default();
+        let mut history = History::default();
+        history.set_tile(&mut level, &mut counter, 0, 0, [1, 0]);
+        history.commit(&level);
+        let saved = history.state();
+        history.set_tile(&mut level, &mut counter, 1, 0, [1, 0]);
+        history.commit(&level);
+        assert_ne!(history.state(), saved);
+
+        history.undo(&mut level, &mut counter);
+        assert_eq!(history.state(), saved);
+        history.undo(&mut level, &mut counter);
+        assert_ne!(history.state(), saved);
+        history.redo(&mut level, &mut counter);
+        assert_eq!(history.state(), saved);
+
+        // branching off before the saved state never leads back to it
+        history.undo(&mut level, &mut counter);
+        history.set_tile(&mut level, &mut counter, 2, 0, [1, 0]);
+        history.commit(&level);
+        assert_ne!(history.state(), saved);
+        assert!(!history.redo(&mut level, &mut counter));
+        history.undo(&mut level, &mut counter);
+        assert_ne!(history.state(), saved);
+        assert!(level == empty_level(4, 4));
+    }
+
+    #[test]
+    fn oldest_steps_are_dropped() {
+        let mut level = empty_level(4, 4);
+        let mut counter = Counter::default();
+        let mut history = History::default();
+        let initial = history.state();
+        for i in 0..=MAX_HISTORY {
+            history.set_tile(&mut level, &mut counter, 0, 0, [(i % 2 + 1) as u8, 0]);
+            history.commit(&level);
+        }
+        let mut steps = 0;
+        while history.undo(&mut level, &mut counter) {
+            steps += 1;
+        }
+        assert_eq!(steps, MAX_HISTORY);
+        assert_eq!(level.tiles[0], [1, 0]);
+        assert_ne!(history.state(), initial);
+    }
+}
@@ -0,0 +1,150 @@
+//! Mod for noticing changes to the asset files on disk while the game is running, in debug builds.
+//!
+//! The actual reloading is done by [Assets::hot_reload](crate::assets::Assets::hot_reload).
+
+use std::{collections::HashMap, path::PathBuf, time::SystemTime};
+
+use macroquad::time::get_time;
+
+use crate::manifest::{MANIFEST_PATH, Manifest};
+
+/// How often (in seconds) the asset files are checked for changes
+const POLL_INTERVAL: f64 = 0.5;
+
+/// The assets directory of the source tree, so it's found no matter where the game is run from
+pub fn assets_dir() -> PathBuf {
+    PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/assets"))
+}
+
+/// Keeps track of which files of a pack changed since they were last loaded.
+///
+/// Files stay changed until they're marked as [reloaded](AssetWatcher::reloaded), so a file that failed to
+/// reload (such as one read while it was still being written) is tried again on the next poll.
+pub struct AssetWatcher {
+    dir: PathBuf,
+    /// Modification time of every file of the pack when it was last loaded, including the manifest
+    loaded: HashMap<String, SystemTime>,
+    /// Modification time of the changed files when they were found, to be recorded once they reload
+    pending: HashMap<String, SystemTime>,
+    /// Modification time of the files that failed to reload, so each failure is only reported once
+    failed: HashMap<String, SystemTime>,
+    last_poll: f64,
+}
+impl AssetWatcher {
+    /// Starts watching the files of a pack in `dir`, as they are now
+    pub fn new(dir: PathBuf, manifest: &Manifest) -> Self {
+        let mut watcher = Self {
+            dir,
+            loaded: HashMap::new(),
+            pending: HashMap::new(),
+            failed: HashMap::new(),
+            last_poll: 0.0,
+        };
+        for path in watcher.changed_files(manifest) {
+            watcher.reloaded(&path);
+        }
+        watcher
+    }
+    fn changed_files(&mut self, manifest: &Manifest) -> Vec<String> {
+        let mut changed = Vec::new();
+        for path in manifest.files().into_iter().chain([MANIFEST_PATH]) {
+            let Ok(modified) = std::fs::metadata(self.dir.join(path)).and_then(|f| f.modified())
+            else {
+                continue;
+            };
+            if self.loaded.get(path) != Some(&modified) {
+                self.pending.insert(path.to_string(), modified);
+                changed.push(path.to_string());
+            }
+        }
+        changed
+    }
+    /// Returns the paths of the files that were modified since they were last loaded.
+    ///
+    /// Only actually checks the files every [POLL_INTERVAL] seconds.
+    pub fn poll(&mut self, manifest: &Manifest) -> Vec<String> {
+        if get_time() - self.last_poll < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = get_time();
+        self.changed_files(manifest)
+    }
+    /// Marks a changed file as loaded, as it was when it was found to have changed
+    pub fn reloaded(&mut self, path: &str) {
+        if let Some(modified) = self.pending.remove(path) {
+            self.loaded.insert(path.to_string(), modified);
+        }
+        self.failed.remove(path);
+    }
+    /// Marks a changed file as failed to reload, so it's tried again on the next poll.
+    ///
+    /// Returns true the first time the file fails as it is now, so the failure can be reported.
+    pub fn failed(&mut self, path: &str) -> bool {
+        let Some(modified) = self.pending.remove(path) else {
+            return false;
+        };
+        self.failed.insert(path.to_string(), modified) != Some(modified)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack::AssetPack;
+
+    /// A directory with a file in it, to be watched as if it was the only file of a pack
+    fn watched_dir(name: &str) -> (PathBuf, Manifest) {
+        let dir = std::env::temp_dir().join(format!("goblin-maker-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("pix32.ttf"), "font").unwrap();
+        let mut manifest = AssetPack::embedded().manifest;
+        manifest.textures.clear();
+        manifest.animations.clear();
+        manifest.animation_groups.clear();
+        manifest.tilesets.clear();
+        (dir, manifest)
+    }
+    fn touch(dir: &std::path::Path, time: u64) {
+        let file = std::fs::File::options()
+            .write(true)
+            .open(dir.join("pix32.ttf"))
+            .unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(time))
+            .unwrap();
+    }
+
+    #[test]
+    fn changed_until_reloaded() {
+        let (dir, manifest) = watched_dir("reloaded");
+        let mut watcher = AssetWatcher::new(dir.clone(), &manifest);
+        assert!(watcher.changed_files(&manifest).is_empty());
+
+        touch(&dir, 1);
+        assert_eq!(watcher.changed_files(&manifest), ["pix32.ttf"]);
+        assert_eq!(watcher.changed_files(&manifest), ["pix32.ttf"]);
+        watcher.reloaded("pix32.ttf");
+        assert!(watcher.changed_files(&manifest).is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failures_are_retried_and_reported_once() {
+        let (dir, manifest) = watched_dir("failed");
+        let mut watcher = AssetWatcher::new(dir.clone(), &manifest);
+
+        touch(&dir, 1);
+        assert_eq!(watcher.changed_files(&manifest), ["pix32.ttf"]);
+        assert!(watcher.failed("pix32.ttf"));
+        assert_eq!(watcher.changed_files(&manifest), ["pix32.ttf"]);
+        assert!(!watcher.failed("pix32.ttf"));
+
+        // the file was written again, so the new failure is reported
+        touch(&dir, 2);
+        assert_eq!(watcher.changed_files(&manifest), ["pix32.ttf"]);
+        assert!(watcher.failed("pix32.ttf"));
+        assert_eq!(watcher.changed_files(&manifest), ["pix32.ttf"]);
+        watcher.reloaded("pix32.ttf");
+        assert!(watcher.changed_files(&manifest).is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
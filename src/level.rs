@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use macroquad::prelude::*;
 use nanoserde::{DeBin, SerBin};
 
@@ -6,14 +8,45 @@ use crate::{
     utils::*,
 };
 
-#[derive(Clone, DeBin, SerBin)]
+#[derive(Clone, PartialEq, DeBin, SerBin)]
 pub enum Character {
     PlayerSpawn,
     Flag,
-    WanderEnemy(usize),
+    WanderEnemy {
+        animation: usize,
+        moving_left: bool,
+        /// Walking speed in pixels per second
+        speed: f32,
+        /// How many tiles the enemy walks away from where it starts before turning around, 0 for no limit
+        patrol_range: u8,
+        /// Whether the enemy turns around instead of walking off ledges
+        turn_at_ledges: bool,
+    },
+}
+impl Character {
+    /// An enemy with the default properties
+    pub fn wander_enemy(animation: usize) -> Self {
+        Character::WanderEnemy {
+            animation,
+            moving_left: true,
+            speed: DEFAULT_ENEMY_SPEED,
+            patrol_range: 0,
+            turn_at_ledges: false,
+        }
+    }
 }
 
-#[derive(Clone, DeBin, SerBin)]
+/// Tiles that are mirror images of each other, as (layer, tile id, mirrored tile id).
+/// These are swapped when a part of a level is flipped, so it looks mirrored and not just rearranged.
+///
+/// No tile of the default tilesets faces left or right, so there's nothing to swap horizontally.
+const HORIZONTAL_MIRRORS: &[(usize, u8, u8)] = &[];
+const VERTICAL_MIRRORS: &[(usize, u8, u8)] = &[
+    // spike pointing up and down
+    (1, 1, 7),
+];
+
+#[derive(Clone, PartialEq, DeBin, SerBin)]
 pub struct Level {
     pub tiles: Vec<[u8; 2]>,
     pub width: usize,
@@ -23,148 +56,863 @@ impl Level {
     pub fn height(&self) -> usize {
         self.tiles.len() / self.width
     }
+    /// Mirrors the level horizontally or vertically, along with directional tiles and enemies
+    pub fn flip(&mut self, horizontal: bool) {
+        let (width, height) = (self.width, self.height());
+        let mirrors = if horizontal {
+            HORIZONTAL_MIRRORS
+        } else {
+            VERTICAL_MIRRORS
+        };
+        let mut tiles = Vec::with_capacity(self.tiles.len());
+        for y in 0..height {
+            for x in 0..width {
+                let (x, y) = if horizontal {
+                    (width - 1 - x, y)
+                } else {
+                    (x, height - 1 - y)
+                };
+                let mut tile = self.tiles[x + y * width];
+                for (layer, a, b) in mirrors {
+                    if tile[*layer] == *a {
+                        tile[*layer] = *b;
+                    } else if tile[*layer] == *b {
+                        tile[*layer] = *a;
+                    }
+                }
+                tiles.push(tile);
+            }
+        }
+        self.tiles = tiles;
+
+        let last = (((width - 1) * 16) as f32, ((height - 1) * 16) as f32);
+        for (pos, character, _) in self.characters.iter_mut() {
+            if horizontal {
+                pos.0 = last.0 - pos.0;
+                if let Character::WanderEnemy { moving_left, .. } = character {
+                    *moving_left = !*moving_left;
+                }
+            } else {
+                pos.1 = last.1 - pos.1;
+            }
+        }
+    }
+    /// Rotates the layout of the level 90 degrees clockwise.
+    ///
+    /// Tiles and enemies keep facing the way they did, as there are no sideways spikes and enemies only walk
+    /// left or right, so a spike pointing up still points up afterwards.
+    pub fn rotate(&mut self) {
+        let (width, height) = (self.width, self.height());
+        let mut tiles = Vec::with_capacity(self.tiles.len());
+        for y in 0..width {
+            for x in 0..height {
+                tiles.push(self.tiles[y + (height - 1 - x) * width]);
+            }
+        }
+        self.tiles = tiles;
+        self.width = height;
+
+        let last = ((height - 1) * 16) as f32;
+        for (pos, _, _) in self.characters.iter_mut() {
+            *pos = (last - pos.1, pos.0);
+        }
+    }
     pub fn get_tile(&self, x: usize, y: usize) -> [u8; 2] {
         if x >= self.width || y >= self.height() {
             return [0, 0];
         }
         self.tiles[x + y * self.width]
     }
+    /// Resizes the level, keeping the side (or corner) given by `anchor` in place.
+    ///
+    /// `anchor` is the column and row of the anchor, each being 0, 1 or 2 for start, center or end.
+    /// Enemies that end up outside the level are removed, while the spawn and flag are moved inside.
+    /// Returns how many tiles the existing contents were offset by.
+    pub fn resize(&mut self, width: usize, height: usize, anchor: (u8, u8)) -> (isize, isize) {
+        let offset = |old: usize, new: usize, anchor: u8| -> isize {
+            let diff = new as isize - old as isize;
+            match anchor {
+                0 => 0,
+                1 => diff / 2,
+                _ => diff,
+            }
+        };
+        let dx = offset(self.width, width, anchor.0);
+        let dy = offset(self.height(), height, anchor.1);
+
+        let mut tiles = vec![[0, 0]; width * height];
+        for (index, tile) in self.tiles.iter().enumerate() {
+            let x = (index % self.width) as isize + dx;
+            let y = (index / self.width) as isize + dy;
+            if (0..width as isize).contains(&x) && (0..height as isize).contains(&y) {
+                tiles[x as usize + y as usize * width] = *tile;
+            }
+        }
+        self.tiles = tiles;
+        self.width = width;
+
+        let size = ((width * 16) as f32, (height * 16) as f32);
+        for (pos, character, _) in self.characters.iter_mut() {
+            pos.0 += (dx * 16) as f32;
+            pos.1 += (dy * 16) as f32;
+            if matches!(character, Character::PlayerSpawn | Character::Flag) {
+                pos.0 = pos.0.clamp(0.0, size.0 - 16.0);
+                pos.1 = pos.1.clamp(0.0, size.1 - 16.0);
+            }
+        }
+        self.characters
+            .retain(|f| (0.0..size.0).contains(&f.0.0) && (0.0..size.1).contains(&f.0.1));
+        (dx, dy)
+    }
+    /// Checks that the level can be safely played and edited
+    pub fn validate(&self, limits: &LevelLimits) -> Result<(), Vec<LevelError>> {
+        let mut errors = Vec::new();
+        // the rest of the checks need the level to have a size
+        if self.width == 0 {
+            return Err(vec![LevelError::ZeroWidth]);
+        } else if !self.tiles.len().is_multiple_of(self.width) {
+            return Err(vec![LevelError::UnevenTiles]);
+        } else if self.width > MAX_LEVEL_SIZE || self.height() > MAX_LEVEL_SIZE {
+            return Err(vec![LevelError::TooLarge]);
+        }
+        for (index, tile_bundle) in self.tiles.iter().enumerate() {
+            for (layer, (id, max)) in tile_bundle
+                .iter()
+                .zip([limits.terrain_tiles, limits.obstacle_tiles])
+                .enumerate()
+            {
+                if *id as usize > max {
+                    errors.push(LevelError::InvalidTile {
+                        layer: layer as u8,
+                        x: index % self.width,
+                        y: index / self.width,
+                        id: *id,
+                    });
+                }
+            }
+        }
+
+        let spawns = self
+            .characters
+            .iter()
+            .filter(|f| matches!(f.1, Character::PlayerSpawn))
+            .count();
+        let flags = self
+            .characters
+            .iter()
+            .filter(|f| matches!(f.1, Character::Flag))
+            .count();
+        match spawns {
+            0 => errors.push(LevelError::MissingSpawn),
+            1 => {}
+            _ => errors.push(LevelError::DuplicateSpawn),
+        }
+        match flags {
+            0 => errors.push(LevelError::MissingFlag),
+            1 => {}
+            _ => errors.push(LevelError::DuplicateFlag),
+        }
+        if spawns == 1
+            && flags == 1
+            && !(matches!(self.characters[0].1, Character::PlayerSpawn)
+                && matches!(self.characters[1].1, Character::Flag))
+        {
+            errors.push(LevelError::MisplacedCharacters);
+        }
+
+        let size = ((self.width * 16) as f32, (self.height() * 16) as f32);
+        for (index, (pos, character, tile)) in self.characters.iter().enumerate() {
+            if *tile >= limits.character_tiles {
+                errors.push(LevelError::InvalidCharacterTile(index));
+            }
+            if !(0.0..size.0).contains(&pos.0) || !(0.0..size.1).contains(&pos.1) {
+                errors.push(LevelError::CharacterOutOfBounds(index));
+            }
+            if let Character::WanderEnemy {
+                animation,
+                speed,
+                patrol_range,
+                ..
+            } = character
+            {
+                if *animation >= limits.enemy_animations {
+                    errors.push(LevelError::UnknownEnemy(index, *animation));
+                }
+                if !(MIN_ENEMY_SPEED..=MAX_ENEMY_SPEED).contains(speed)
+                    || *patrol_range > MAX_PATROL_RANGE
+                {
+                    errors.push(LevelError::InvalidEnemyProperties(index));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+    /// Tile that a character at a position mostly covers, if it is within the level
+    fn character_tile(&self, pos: (f32, f32)) -> Option<(usize, usize)> {
+        let (x, y) = ((pos.0 + 8.0) / 16.0, (pos.1 + 8.0) / 16.0);
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let (x, y) = (x as usize, y as usize);
+        (x < self.width && y < self.height()).then_some((x, y))
+    }
+    /// Finds likely mistakes that make the level unplayable, like characters stuck inside terrain.
+    /// Unlike [Level::validate], these don't stop the level from being saved or played.
+    pub fn lint(&self) -> Vec<LevelWarning> {
+        let mut warnings = Vec::new();
+        let mut spawn = None;
+        let mut flag = None;
+        for (pos, character, _) in self.characters.iter() {
+            let Some((x, y)) = self.character_tile(*pos) else {
+                continue;
+            };
+            let tile = self.get_tile(x, y);
+            match character {
+                Character::PlayerSpawn => {
+                    if tile[0] != 0 {
+                        warnings.push(LevelWarning::SpawnInTerrain(x, y));
+                    } else {
+                        spawn = Some((x, y));
+                    }
+                }
+                Character::Flag => {
+                    if tile[0] != 0 || tile[1] != 0 {
+                        warnings.push(LevelWarning::FlagBlocked(x, y));
+                    } else {
+                        flag = Some((x, y));
+                    }
+                }
+                Character::WanderEnemy { .. } => {
+                    if tile[0] != 0 {
+                        warnings.push(LevelWarning::EnemyInTerrain(x, y));
+                    }
+                }
+            }
+        }
+
+        // walk through all open tiles connected to the spawn, to see if the flag is walled off
+        if let (Some(spawn), Some(flag)) = (spawn, flag) {
+            let (width, height) = (self.width, self.height());
+            let mut visited = vec![false; width * height];
+            let mut stack = vec![spawn];
+            visited[spawn.0 + spawn.1 * width] = true;
+            while let Some((x, y)) = stack.pop() {
+                for (x, y) in [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ] {
+                    if x < width
+                        && y < height
+                        && !visited[x + y * width]
+                        && self.tiles[x + y * width][0] == 0
+                    {
+                        visited[x + y * width] = true;
+                        stack.push((x, y));
+                    }
+                }
+            }
+            if !visited[flag.0 + flag.1 * width] {
+                warnings.push(LevelWarning::FlagUnreachable(flag.0, flag.1));
+            }
+        }
+        warnings
+    }
+}
+
+/// Likely mistakes in a level, found by [Level::lint]. Each holds the tile it concerns.
+pub enum LevelWarning {
+    SpawnInTerrain(usize, usize),
+    /// Flag is inside terrain or an obstacle
+    FlagBlocked(usize, usize),
+    EnemyInTerrain(usize, usize),
+    /// Flag is walled off from the spawn
+    FlagUnreachable(usize, usize),
+}
+impl LevelWarning {
+    pub fn tile(&self) -> (usize, usize) {
+        match self {
+            LevelWarning::SpawnInTerrain(x, y)
+            | LevelWarning::FlagBlocked(x, y)
+            | LevelWarning::EnemyInTerrain(x, y)
+            | LevelWarning::FlagUnreachable(x, y) => (*x, *y),
+        }
+    }
+}
+impl Display for LevelWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (x, y) = self.tile();
+        match self {
+            LevelWarning::SpawnInTerrain(..) => write!(f, "spawn is inside terrain at {x},{y}"),
+            LevelWarning::FlagBlocked(..) => write!(f, "flag is blocked at {x},{y}"),
+            LevelWarning::EnemyInTerrain(..) => write!(f, "enemy is inside terrain at {x},{y}"),
+            LevelWarning::FlagUnreachable(..) => write!(f, "flag at {x},{y} is walled off"),
+        }
+    }
+}
+
+/// Upper bounds for the ids a level may reference, taken from the loaded assets.
+pub struct LevelLimits {
+    pub terrain_tiles: usize,
+    pub obstacle_tiles: usize,
+    pub character_tiles: usize,
+    pub enemy_animations: usize,
+}
+impl LevelLimits {
+    pub fn new(assets: &Assets) -> Self {
+        Self {
+            terrain_tiles: assets.terrain_tileset().get_tiles().len(),
+            obstacle_tiles: assets.obstacles_tileset().get_tiles().len(),
+            character_tiles: assets.character_tileset().get_tiles().len(),
+            enemy_animations: assets.enemies().animations.len(),
+        }
+    }
+}
+
+pub enum LevelError {
+    ZeroWidth,
+    UnevenTiles,
+    /// Width or height is above [MAX_LEVEL_SIZE]
+    TooLarge,
+    MissingSpawn,
+    DuplicateSpawn,
+    MissingFlag,
+    DuplicateFlag,
+    /// Spawn and flag must be the first and second character respectively
+    MisplacedCharacters,
+    InvalidTile {
+        layer: u8,
+        x: usize,
+        y: usize,
+        id: u8,
+    },
+    InvalidCharacterTile(usize),
+    CharacterOutOfBounds(usize),
+    UnknownEnemy(usize, usize),
+    /// Speed or patrol range of an enemy is out of range
+    InvalidEnemyProperties(usize),
+}
+impl Display for LevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LevelError::ZeroWidth => write!(f, "level has zero width"),
+            LevelError::UnevenTiles => write!(f, "tile count doesn't match level width"),
+            LevelError::TooLarge => write!(f, "level is larger than {MAX_LEVEL_SIZE} tiles"),
+            LevelError::MissingSpawn => write!(f, "level has no player spawn"),
+            LevelError::DuplicateSpawn => write!(f, "level has multiple player spawns"),
+            LevelError::MissingFlag => write!(f, "level has no flag"),
+            LevelError::DuplicateFlag => write!(f, "level has multiple flags"),
+            LevelError::MisplacedCharacters => write!(f, "spawn and flag are out of order"),
+            LevelError::InvalidTile { layer, x, y, id } => {
+                let layer = if *layer == 0 { "terrain" } else { "obstacle" };
+                write!(f, "unknown {layer} tile {id} at {x},{y}")
+            }
+            LevelError::InvalidCharacterTile(index) => {
+                write!(f, "character {index} has an unknown sprite")
+            }
+            LevelError::CharacterOutOfBounds(index) => {
+                write!(f, "character {index} is out of bounds")
+            }
+            LevelError::UnknownEnemy(index, animation) => {
+                write!(f, "character {index} is unknown enemy {animation}")
+            }
+            LevelError::InvalidEnemyProperties(index) => {
+                write!(f, "character {index} has invalid enemy settings")
+            }
+        }
+    }
+}
+/// Formats a list of errors for displaying in a popup
+pub fn describe_errors(errors: &[LevelError]) -> String {
+    const MAX_LINES: usize = 3;
+    let mut lines: Vec<String> = errors
+        .iter()
+        .take(MAX_LINES)
+        .map(|f| f.to_string())
+        .collect();
+    if errors.len() > MAX_LINES {
+        lines.push(format!("...and {} more", errors.len() - MAX_LINES));
+    }
+    lines.join("\n")
+}
+
+/// A rendered chunk of a level
+struct Chunk {
+    camera: Camera2D,
+    /// Size in pixels that each tile was rendered at
+    tile_pixels: u32,
+    /// Last update the chunk was in view, for freeing the least recently used chunks first
+    last_used: u64,
+}
+impl Chunk {
+    /// Memory used by the texture of the chunk, in bytes
+    fn memory(&self) -> usize {
+        let texture = &self.camera.render_target.as_ref().unwrap().texture;
+        texture.width() as usize * texture.height() as usize * 4
+    }
 }
 
+/// Caches the rendered tiles of a level in square chunks of [LEVEL_CHUNK_SIZE] tiles,
+/// so large levels don't need a single huge texture.
+///
+/// Chunks are only rendered once they come into view, and are rerendered when their tiles change.
+/// When zoomed out, chunks are rendered at a lower resolution, and once the chunks use more than
+/// [MAX_CHUNK_MEMORY], the ones that have been out of view the longest are freed.
 pub struct LevelRenderer<'a> {
     pub assets: &'a Assets,
-    pub camera: Camera2D,
+    /// Size of the level in pixels
     pub size: Vec2,
     pub clear_color: Color,
+    /// Which of the terrain and obstacle layers are drawn
+    pub visible_layers: [bool; 2],
+    /// Width of the level in chunks
+    chunks_width: usize,
+    chunks: Vec<Option<Chunk>>,
+    /// Chunks that need to be rendered again before they're drawn
+    dirty: Vec<bool>,
+    /// Generation of the assets the chunks were rendered with, see [Assets::generation]
+    generation: u32,
+    /// Memory used by all rendered chunks, in bytes
+    memory: usize,
+    /// How many times the chunks were updated, used as a clock for [Chunk::last_used]
+    updates: u64,
 }
 impl<'a> LevelRenderer<'a> {
+    /// Draws a tile of the level, picking the right variant if it is autotiled
     fn draw_tile(
         level: &Level,
+        layer: usize,
         level_pos: (usize, usize),
-        screen: Vec2,
-        tile: Vec2,
+        id: u8,
         spritesheet: &Spritesheet,
     ) {
-        if tile == Vec2::ZERO
-            && let Some((autotile_hashmap, autotile_tileset)) = &spritesheet.autotile_first
+        let screen = vec2((level_pos.0 * 16) as f32, (level_pos.1 * 16) as f32);
+        if let Some(autotile) = spritesheet.autotile_for(id)
+            && let Some(pos) = autotile.rules.variant(autotile.rules.neighbour_mask(
+                level,
+                layer,
+                level_pos.0,
+                level_pos.1,
+            ))
         {
-            let sides = [
-                level.get_tile(level_pos.0, level_pos.1.saturating_sub(1))[0] == 1,
-                level.get_tile(level_pos.0.saturating_sub(1), level_pos.1)[0] == 1,
-                level.get_tile(level_pos.0 + 1, level_pos.1)[0] == 1,
-                level.get_tile(level_pos.0, level_pos.1 + 1)[0] == 1,
-            ];
-            let pos = autotile_hashmap.get(&sides).unwrap();
-            autotile_tileset.draw_tile(screen.x, screen.y, pos.x / 16.0, pos.y / 16.0, None);
+            autotile
+                .spritesheet
+                .draw_tile(screen.x, screen.y, pos.x, pos.y, None);
             return;
         }
 
-        spritesheet.draw_tile(screen.x, screen.y, tile.x, tile.y, None);
-    }
-    pub fn set_tile(&mut self, level: &mut Level, x: usize, y: usize, tile_bundle: [u8; 2]) {
-        level.tiles[x + y * level.width] = tile_bundle;
-        set_camera(&self.camera);
-        draw_rectangle(
-            (x * 16) as f32,
-            (y * 16) as f32,
-            16.0,
-            16.0,
-            self.clear_color,
+        let tile = id - 1;
+        spritesheet.draw_tile(
+            screen.x,
+            screen.y,
+            (tile % 3) as f32,
+            (tile / 3) as f32,
+            None,
         );
-
-        for (index, (tile, spritesheet)) in tile_bundle
-            .into_iter()
-            .zip([&self.assets.terrain_tileset, &self.assets.obstacles_tileset].iter())
-            .enumerate()
-        {
-            let tile_positions = [
-                (x, y.saturating_sub(1)),
-                (x.saturating_sub(1), y),
+    }
+    /// Marks tiles that were changed directly in the level to be redrawn, along with their neighbours,
+    /// so that autotiled edges update.
+    pub fn redraw_tiles(&mut self, level: &Level, positions: &[(usize, usize)]) {
+        for &(x, y) in positions {
+            for (x, y) in [
+                (x.wrapping_sub(1), y.wrapping_sub(1)),
+                (x, y.wrapping_sub(1)),
+                (x + 1, y.wrapping_sub(1)),
+                (x.wrapping_sub(1), y),
+                (x, y),
                 (x + 1, y),
+                (x.wrapping_sub(1), y + 1),
                 (x, y + 1),
-            ];
-            if index == 0 {
-                for (x, y) in tile_positions {
-                    if y >= level.height() {
-                        continue;
-                    }
-                    if x >= level.width {
-                        continue;
-                    }
-                    let tile = level.tiles[x + y * level.width];
-                    if tile[0] == 0 {
+                (x + 1, y + 1),
+            ] {
+                if x < level.width && y < level.height() {
+                    let index = x / LEVEL_CHUNK_SIZE + y / LEVEL_CHUNK_SIZE * self.chunks_width;
+                    self.dirty[index] = true;
+                }
+            }
+        }
+    }
+    /// Area of a chunk in pixels
+    fn chunk_rect(&self, index: usize) -> Rect {
+        let chunk_pixels = (LEVEL_CHUNK_SIZE * 16) as f32;
+        let pos = vec2(
+            (index % self.chunks_width) as f32,
+            (index / self.chunks_width) as f32,
+        ) * chunk_pixels;
+        let size = (self.size - pos).min(Vec2::splat(chunk_pixels));
+        Rect::new(pos.x, pos.y, size.x, size.y)
+    }
+    /// Indices of the chunks that overlap an area (in pixels) of the level
+    fn chunks_in_view(&self, view: Rect) -> impl Iterator<Item = usize> + use<> {
+        let chunk_pixels = (LEVEL_CHUNK_SIZE * 16) as f32;
+        let chunks_height = self.chunks.len() / self.chunks_width;
+        let chunks_width = self.chunks_width;
+        let start_x = (view.x / chunk_pixels).floor().max(0.0) as usize;
+        let start_y = (view.y / chunk_pixels).floor().max(0.0) as usize;
+        let end_x = (((view.x + view.w) / chunk_pixels).ceil().max(0.0) as usize).min(chunks_width);
+        let end_y =
+            (((view.y + view.h) / chunk_pixels).ceil().max(0.0) as usize).min(chunks_height);
+        (start_y..end_y).flat_map(move |y| (start_x..end_x).map(move |x| x + y * chunks_width))
+    }
+    fn render_chunk(&mut self, level: &Level, index: usize, tile_pixels: u32) {
+        let rect = self.chunk_rect(index);
+        if self.chunks[index]
+            .as_ref()
+            .is_none_or(|f| f.tile_pixels != tile_pixels)
+        {
+            let resolution = tile_pixels as f32 / 16.0;
+            let mut camera = create_camera(rect.w * resolution, rect.h * resolution);
+            // the camera still covers the chunk in level pixels, just with fewer texture pixels
+            camera.zoom = vec2(2.0 / rect.w, 2.0 / rect.h);
+            camera.target = rect.center();
+            let chunk = Chunk {
+                camera,
+                tile_pixels,
+                last_used: self.updates,
+            };
+            self.memory += chunk.memory();
+            if let Some(old) = self.chunks[index].replace(chunk) {
+                self.memory -= old.memory();
+            }
+        }
+        set_camera(&self.chunks[index].as_ref().unwrap().camera);
+        clear_background(self.clear_color);
+
+        let start = (rect.x as usize / 16, rect.y as usize / 16);
+        let end = (
+            start.0 + rect.w as usize / 16,
+            start.1 + rect.h as usize / 16,
+        );
+        for y in start.1..end.1 {
+            for x in start.0..end.0 {
+                for (layer, ((tile, tileset), visible)) in level.tiles[x + y * level.width]
+                    .into_iter()
+                    .zip([
+                        self.assets.terrain_tileset(),
+                        self.assets.obstacles_tileset(),
+                    ])
+                    .zip(self.visible_layers)
+                    .enumerate()
+                {
+                    if tile == 0 || !visible {
                         continue;
                     }
-                    Self::draw_tile(
-                        level,
-                        (x, y),
-                        vec2((x * 16) as f32, (y * 16) as f32),
-                        vec2(((tile[0] - 1) % 3) as f32, ((tile[0] - 1) / 3) as f32),
-                        &self.assets.terrain_tileset,
-                    );
-                    if tile[1] != 0 {
-                        Self::draw_tile(
-                            level,
-                            (x, y),
-                            vec2((x * 16) as f32, (y * 16) as f32),
-                            vec2(((tile[1] - 1) % 3) as f32, ((tile[1] - 1) / 3) as f32),
-                            &self.assets.obstacles_tileset,
-                        );
-                    }
+                    Self::draw_tile(level, layer, (x, y), tile, tileset);
                 }
             }
-            if tile == 0 {
-                continue;
+        }
+        self.dirty[index] = false;
+    }
+    /// Frees the chunks that have been out of view the longest, until the rest fit in [MAX_CHUNK_MEMORY]
+    fn free_chunks(&mut self) {
+        if self.memory <= MAX_CHUNK_MEMORY {
+            return;
+        }
+        let mut unused: Vec<(u64, usize)> = self
+            .chunks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, chunk)| {
+                let chunk = chunk.as_ref()?;
+                (chunk.last_used < self.updates).then_some((chunk.last_used, index))
+            })
+            .collect();
+        unused.sort_unstable();
+        for (_, index) in unused {
+            if self.memory <= MAX_CHUNK_MEMORY {
+                break;
             }
-            let tile = tile - 1;
-
-            Self::draw_tile(
-                level,
-                (x, y),
-                vec2((x * 16) as f32, (y * 16) as f32),
-                vec2((tile % 3) as f32, (tile / 3) as f32),
-                spritesheet,
-            );
+            self.memory -= self.chunks[index].take().unwrap().memory();
         }
     }
-    pub fn draw_level(level: &Level, assets: &Assets) {
-        for (index, tile_bundle) in level.tiles.iter().enumerate() {
-            for (tile, tileset) in tile_bundle
-                .iter()
-                .zip([&assets.terrain_tileset, &assets.obstacles_tileset].iter())
+    /// Renders any chunks within an area (in pixels) of the level that are missing or out of date.
+    /// `scale` is what the chunks will be drawn at (see [LevelRenderer::draw]),
+    /// and picks the resolution they are rendered at.
+    ///
+    /// This changes the active camera, so it should be called before setting up the camera to draw with.
+    pub fn update_chunks(&mut self, level: &Level, view: Rect, scale: f32) {
+        if self.assets.generation() != self.generation {
+            self.generation = self.assets.generation();
+            self.dirty.fill(true);
+        }
+        self.updates += 1;
+        // the smallest power of two tile size that is still at least as sharp as the tiles on screen
+        let tile_pixels = ((16.0 * scale).ceil().clamp(1.0, 16.0) as u32).next_power_of_two();
+        for index in self.chunks_in_view(view) {
+            if self.dirty[index]
+                || self.chunks[index]
+                    .as_ref()
+                    .is_none_or(|f| f.tile_pixels != tile_pixels)
             {
-                if *tile == 0 {
-                    continue;
-                }
-                let tile = tile - 1;
-                Self::draw_tile(
-                    level,
-                    ((index % level.width), (index / level.width)),
-                    vec2(
-                        ((index % level.width) * 16) as f32,
-                        ((index / level.width) * 16) as f32,
-                    ),
-                    vec2((tile % 3) as f32, (tile / 3) as f32),
-                    tileset,
-                );
+                self.render_chunk(level, index, tile_pixels);
             }
+            self.chunks[index].as_mut().unwrap().last_used = self.updates;
+        }
+        self.free_chunks();
+    }
+    /// Draws the chunks within an area (in pixels) of the level,
+    /// with the level's top left corner at `origin` and scaled by `scale`.
+    pub fn draw(&self, view: Rect, origin: Vec2, scale: f32) {
+        for index in self.chunks_in_view(view) {
+            let Some(chunk) = &self.chunks[index] else {
+                continue;
+            };
+            let rect = self.chunk_rect(index);
+            draw_texture_ex(
+                &chunk.camera.render_target.as_ref().unwrap().texture,
+                origin.x + rect.x * scale,
+                origin.y + rect.y * scale,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(rect.size() * scale),
+                    ..Default::default()
+                },
+            );
         }
     }
     pub fn new(level: &Level, assets: &'a Assets, clear_color: Color) -> Self {
-        let mut camera = create_camera((level.width * 16) as f32, (level.height() * 16) as f32);
-        camera.target = vec2(
-            (level.width * 16) as f32 / 2.0,
-            (level.height() * 16) as f32 / 2.0,
-        );
-        set_camera(&camera);
-        clear_background(clear_color);
-
-        Self::draw_level(level, assets);
-        Self {
+        let mut renderer = Self {
             assets,
-            size: vec2((level.width * 16) as f32, (level.height() * 16) as f32),
-            camera,
+            size: Vec2::ZERO,
             clear_color,
+            visible_layers: [true; 2],
+            chunks_width: 0,
+            chunks: Vec::new(),
+            dirty: Vec::new(),
+            generation: assets.generation(),
+            memory: 0,
+            updates: 0,
+        };
+        renderer.rebuild(level);
+        renderer
+    }
+    /// Marks the whole level to be redrawn, keeping the current settings.
+    ///
+    /// Needed when the size of the level changes, or when a layer is shown or hidden.
+    pub fn rebuild(&mut self, level: &Level) {
+        let size = vec2((level.width * 16) as f32, (level.height() * 16) as f32);
+        if size != self.size {
+            self.size = size;
+            self.chunks_width = level.width.div_ceil(LEVEL_CHUNK_SIZE);
+            let chunks = self.chunks_width * level.height().div_ceil(LEVEL_CHUNK_SIZE);
+            self.chunks = (0..chunks).map(|_| None).collect();
+            self.dirty = vec![true; chunks];
+            self.memory = 0;
+        } else {
+            self.dirty.fill(true);
+        }
+    }
+}
+
+/// Levels shared by the tests of every module
+#[cfg(test)]
+pub mod test_levels {
+    use super::{Character, Level};
+
+    /// An empty level with the player spawn in the top left corner and the flag next to it
+    pub fn empty_level(width: usize, height: usize) -> Level {
+        Level {
+            tiles: vec![[0, 0]; width * height],
+            width,
+            characters: vec![
+                ((0.0, 0.0), Character::PlayerSpawn, 0),
+                ((16.0, 0.0), Character::Flag, 1),
+            ],
+        }
+    }
+
+    /// An 8x6 level with a floor, one obstacle and an enemy between the spawn and the flag
+    pub fn test_level() -> Level {
+        let mut tiles = vec![[0, 0]; 8 * 6];
+        tiles[5 * 8..].fill([1, 0]);
+        tiles[4 * 8 + 3] = [0, 1];
+        Level {
+            tiles,
+            width: 8,
+            characters: vec![
+                ((16.0, 64.0), Character::PlayerSpawn, 0),
+                ((96.0, 64.0), Character::Flag, 1),
+                ((48.0, 64.0), Character::wander_enemy(0), 2),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test_levels::test_level, *};
+
+    const LIMITS: LevelLimits = LevelLimits {
+        terrain_tiles: 9,
+        obstacle_tiles: 9,
+        character_tiles: 9,
+        enemy_animations: 2,
+    };
+
+    #[test]
+    fn valid_level() {
+        assert!(test_level().validate(&LIMITS).is_ok());
+    }
+
+    #[test]
+    fn zero_width() {
+        let mut level = test_level();
+        level.width = 0;
+        let errors = level.validate(&LIMITS).err().unwrap();
+        assert!(matches!(errors[..], [LevelError::ZeroWidth]));
+
+        let level = Level {
+            tiles: Vec::new(),
+            width: 0,
+            characters: Vec::new(),
+        };
+        let errors = level.validate(&LIMITS).err().unwrap();
+        assert!(matches!(errors[..], [LevelError::ZeroWidth]));
+    }
+
+    #[test]
+    fn uneven_tiles() {
+        let mut level = test_level();
+        level.tiles.pop();
+        let errors = level.validate(&LIMITS).err().unwrap();
+        assert!(matches!(errors[..], [LevelError::UnevenTiles]));
+    }
+
+    #[test]
+    fn too_large() {
+        for (width, height) in [(MAX_LEVEL_SIZE + 1, 4), (4, MAX_LEVEL_SIZE + 1)] {
+            let level = Level {
+                tiles: vec![[0, 0]; width * height],
+                width,
+                characters: Vec::new(),
+            };
+            let errors = level.validate(&LIMITS).err().unwrap();
+            assert!(matches!(errors[..], [LevelError::TooLarge]));
+        }
+        let mut level = test_level();
+        level.resize(MAX_LEVEL_SIZE, MAX_LEVEL_SIZE, (0, 2));
+        assert!(level.validate(&LIMITS).is_ok());
+    }
+
+    #[test]
+    fn invalid_contents() {
+        let mut level = test_level();
+        level.tiles[3] = [10, 0];
+        level.characters.swap(0, 1);
+        level.characters[0].0 = (128.0, 0.0);
+        level
+            .characters
+            .push(((0.0, 0.0), Character::wander_enemy(2), 0));
+        let errors = level.validate(&LIMITS).err().unwrap();
+        assert!(matches!(
+            errors[..],
+            [
+                LevelError::InvalidTile {
+                    layer: 0,
+                    x: 3,
+                    y: 0,
+                    id: 10
+                },
+                LevelError::MisplacedCharacters,
+                LevelError::CharacterOutOfBounds(0),
+                LevelError::UnknownEnemy(3, 2),
+            ]
+        ));
+    }
+
+    #[test]
+    fn lint_walled_off_flag() {
+        let mut level = test_level();
+        assert!(level.lint().is_empty());
+        for y in 0..5 {
+            level.tiles[4 + y * 8] = [1, 0];
+        }
+        let warnings = level.lint();
+        assert!(matches!(
+            warnings[..],
+            [LevelWarning::FlagUnreachable(6, 4)]
+        ));
+    }
+
+    #[test]
+    fn lint_characters_at_the_edge() {
+        // characters in the last half of the last tile mostly cover a tile outside the level
+        let mut level = test_level();
+        let last = (8.0 * 16.0 - 1.0, 6.0 * 16.0 - 1.0);
+        level.characters[0].0 = last;
+        level.characters[1].0 = (last.0, 0.0);
+        level
+            .characters
+            .push(((0.0, last.1), Character::wander_enemy(0), 0));
+        assert!(level.validate(&LIMITS).is_ok());
+        assert!(level.lint().is_empty());
+    }
+
+    fn moving_left(level: &Level) -> Vec<bool> {
+        level
+            .characters
+            .iter()
+            .filter_map(|f| match f.1 {
+                Character::WanderEnemy { moving_left, .. } => Some(moving_left),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flip_horizontally() {
+        let mut level = test_level();
+        level.tiles[0] = [0, 1];
+        let original = level.clone();
+        level.flip(true);
+        // nothing faces sideways, so tiles only move
+        assert_eq!(level.get_tile(7, 0), [0, 1]);
+        assert_eq!(level.get_tile(4, 4), [0, 1]);
+        assert_eq!(level.get_tile(0, 5), [1, 0]);
+        let positions: Vec<_> = level.characters.iter().map(|f| f.0).collect();
+        assert_eq!(positions, [(96.0, 64.0), (16.0, 64.0), (64.0, 64.0)]);
+        assert_eq!(moving_left(&level), [false]);
+        level.flip(true);
+        assert!(level == original);
+    }
+
+    #[test]
+    fn flip_vertically() {
+        let mut level = test_level();
+        level.tiles[0] = [0, 7];
+        let original = level.clone();
+        level.flip(false);
+        // spikes pointing up point down afterwards, and the other way around
+        assert_eq!(level.get_tile(3, 1), [0, 7]);
+        assert_eq!(level.get_tile(0, 5), [0, 1]);
+        assert_eq!(level.get_tile(0, 0), [1, 0]);
+        let positions: Vec<_> = level.characters.iter().map(|f| f.0).collect();
+        assert_eq!(positions, [(16.0, 16.0), (96.0, 16.0), (48.0, 16.0)]);
+        assert_eq!(moving_left(&level), [true]);
+        level.flip(false);
+        assert!(level == original);
+    }
+
+    #[test]
+    fn rotate() {
+        let mut level = test_level();
+        level.rotate();
+        assert_eq!((level.width, level.height()), (6, 8));
+        // the floor along the bottom ends up along the left side, with spikes still pointing up
+        assert!((0..8).all(|y| level.get_tile(0, y) == [1, 0]));
+        assert_eq!(level.get_tile(1, 3), [0, 1]);
+        let positions: Vec<_> = level.characters.iter().map(|f| f.0).collect();
+        assert_eq!(positions, [(16.0, 16.0), (16.0, 96.0), (16.0, 48.0)]);
+        assert_eq!(moving_left(&level), [true]);
+        for _ in 0..3 {
+            level.rotate();
         }
+        assert!(level == test_level());
     }
 }
@@ -3,6 +3,7 @@ use macroquad::prelude::*;
 use crate::{
     assets::Assets,
     data::Data,
+    level::Level,
     maker::*,
     menu::{MainMenu, MenuUpdateResult},
     runtime::*,
@@ -10,12 +11,20 @@ use crate::{
 };
 
 mod assets;
+mod autotile;
 mod data;
+mod format;
+mod history;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+mod hot_reload;
 mod level;
 mod maker;
+mod manifest;
 mod menu;
+mod pack;
 mod player;
 mod runtime;
+mod thumbnail;
 mod ui;
 mod utils;
 
@@ -27,26 +36,70 @@ struct GameManager<'a> {
     menu: MainMenu<'a>,
     runtime: Option<GoblinRuntime<'a>>,
     maker: Option<GoblinMaker<'a>>,
+    /// Copied level contents, kept between levels
+    clipboard: Option<Level>,
+    /// Closing the window was requested while the editor had unsaved changes,
+    /// so the game quits once the editor is exited
+    quit_requested: bool,
+    quit: bool,
 }
 
 impl<'a> GameManager<'a> {
     fn new(assets: &'a Assets) -> Self {
+        let data = Data::load();
         Self {
-            data: Data::load(),
-            menu: MainMenu::new(assets),
+            menu: MainMenu::new(assets, &data),
+            data,
             maker: None,
+            clipboard: None,
+            quit_requested: false,
+            quit: false,
             assets,
             runtime: None,
         }
     }
+    fn level_names(&self) -> Vec<String> {
+        self.data
+            .local
+            .user_levels
+            .iter()
+            .map(|f| f.0.clone())
+            .collect()
+    }
+    /// Handles closing the window, asking to save first if the editor has unsaved changes
+    #[cfg(not(target_arch = "wasm32"))]
+    fn handle_quit_request(&mut self) {
+        if is_quit_requested() {
+            if let Some(maker) = &mut self.maker
+                && maker.modified
+            {
+                self.runtime = None;
+                maker.confirm_exit();
+                self.quit_requested = true;
+            } else {
+                if self.maker.is_some() && self.data.recovery.is_some() {
+                    self.data.set_recovery(None);
+                }
+                self.quit = true;
+            }
+        }
+        if let Some(maker) = &self.maker
+            && !maker.is_confirming_exit()
+        {
+            // exiting was cancelled
+            self.quit_requested = false;
+        }
+    }
     fn update(&mut self) {
         self.data.update();
+        #[cfg(not(target_arch = "wasm32"))]
+        self.handle_quit_request();
         if let Some(runtime) = &mut self.runtime {
             let result = runtime.update();
             if !matches!(result, RuntimeResult::None) {
                 if matches!(result, RuntimeResult::Win) {
                     if let Some(maker) = &mut self.maker {
-                        maker.verified = true
+                        maker.set_verified()
                     } else {
                         let details = self
                             .runtime
@@ -58,7 +111,7 @@ impl<'a> GameManager<'a> {
                         let id = format!("{}-{}", details.0, details.1);
                         if !self.data.local.completed_online_levels.contains(&id) {
                             self.data.local.completed_online_levels.push(id);
-                            self.data.local.store();
+                            self.data.store();
                         }
                     }
                 }
@@ -79,10 +132,16 @@ impl<'a> GameManager<'a> {
                             .verified_levels
                             .insert(name.clone(), maker.verified);
                     }
+                    self.clipboard = maker.clipboard.take();
                     self.maker = None;
+                    if self.data.recovery.is_some() {
+                        self.data.set_recovery(None);
+                    }
+                    self.quit |= self.quit_requested;
                 }
                 MakerUpdateResult::SaveAndExit => {
                     let maker = self.maker.take().unwrap();
+                    self.clipboard = maker.clipboard;
                     let level = maker.level;
                     let name = if let Some(name) = maker.name {
                         name
@@ -103,13 +162,24 @@ impl<'a> GameManager<'a> {
                             .verified_levels
                             .insert(name.clone(), maker.verified);
                     }
+                    self.data.snapshot_level(&name, &level);
                     if let Some(old) = self.data.local.user_levels.iter_mut().find(|f| f.0 == name)
                     {
                         old.1 = level;
                     } else {
                         self.data.local.user_levels.push((name, level));
                     }
-                    self.data.local.store();
+                    self.data.store();
+                    self.data.set_recovery(None);
+                    self.quit |= self.quit_requested;
+                }
+                MakerUpdateResult::StampsChanged => {
+                    self.data.local.stamps = maker.stamps.clone();
+                    self.data.store();
+                }
+                MakerUpdateResult::Autosave => {
+                    self.data
+                        .set_recovery(Some((maker.name.clone(), maker.level.clone())));
                 }
                 MakerUpdateResult::None => {}
             }
@@ -118,11 +188,25 @@ impl<'a> GameManager<'a> {
             let result = self.menu.update(&mut self.data);
             match result {
                 MenuUpdateResult::Create(value) => {
-                    if let Some(index) = value {
+                    let mut maker = if let Some(index) = value {
                         let data = self.data.local.user_levels[index].clone();
-                        self.maker = Some(GoblinMaker::from(self.assets, data.1, Some(data.0)));
+                        GoblinMaker::from(self.assets, data.1, Some(data.0))
                     } else {
-                        self.maker = Some(GoblinMaker::new(self.assets));
+                        GoblinMaker::new(self.assets)
+                    };
+                    maker.clipboard = self.clipboard.take();
+                    maker.stamps = self.data.local.stamps.clone();
+                    maker.level_names = self.level_names();
+                    self.maker = Some(maker);
+                }
+                MenuUpdateResult::Recover => {
+                    if let Some((name, level)) = self.data.recovery.clone() {
+                        let mut maker = GoblinMaker::from(self.assets, level, name);
+                        maker.set_unsaved();
+                        maker.clipboard = self.clipboard.take();
+                        maker.stamps = self.data.local.stamps.clone();
+                        maker.level_names = self.level_names();
+                        self.maker = Some(maker);
                     }
                 }
                 MenuUpdateResult::PlayOnline(level, name, author) => {
@@ -159,8 +243,16 @@ async fn main() {
     println!("goblin maker v{VERSION}");
     let assets = Assets::default();
     let mut game = GameManager::new(&assets);
+    // closing the window is handled by the game, so unsaved changes can be saved first
+    #[cfg(not(target_arch = "wasm32"))]
+    prevent_quit();
     loop {
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        assets.hot_reload();
         game.update();
+        if game.quit {
+            break;
+        }
         next_frame().await
     }
 }
@@ -1,6 +1,8 @@
 use crate::{
-    assets::{Assets, Spritesheet},
-    level::{Character, Level, LevelRenderer},
+    assets::{Assets, Spritesheet, ids},
+    data::Data,
+    history::History,
+    level::{Character, Level, LevelLimits, LevelRenderer, LevelWarning, describe_errors},
     ui::*,
     utils::*,
 };
@@ -20,6 +22,128 @@ enum Tool {
     Pencil,
     Eraser,
     Shape,
+    Fill,
+    Select,
+}
+
+#[derive(Sequence, PartialEq, Eq, Clone, Copy)]
+enum ShapeMode {
+    Rect,
+    HollowRect,
+    Line,
+    Ellipse,
+    HollowEllipse,
+}
+
+#[derive(Sequence, PartialEq, Eq, Clone, Copy)]
+enum EraserMode {
+    Brush,
+    /// Erases a dragged out area of the chosen layer
+    Area,
+    /// Erases a dragged out area of all layers and characters
+    AreaAll,
+}
+
+/// Animation ids of the buttons for each [Tool], [ShapeMode] and [EraserMode], in order
+const TOOL_BTNS: [&str; 5] = [
+    ids::PENCIL_BTN,
+    ids::ERASER_BTN,
+    ids::SHAPE_BTN,
+    ids::FILL_BTN,
+    ids::SELECT_BTN,
+];
+const SHAPE_MODE_BTNS: [&str; 5] = [
+    ids::SHAPE_BTN,
+    ids::HOLLOW_RECT_BTN,
+    ids::LINE_BTN,
+    ids::ELLIPSE_BTN,
+    ids::HOLLOW_ELLIPSE_BTN,
+];
+const ERASER_MODE_BTNS: [&str; 3] = [ids::ERASER_BTN, ids::ERASE_AREA_BTN, ids::ERASE_ALL_BTN];
+/// Animation ids of the buttons for flipping horizontally, vertically and rotating the selection
+const TRANSFORM_BTNS: [&str; 3] = [ids::FLIP_H_BTN, ids::FLIP_V_BTN, ids::ROTATE_BTN];
+
+/// Whether transformed contents of a selection still fit the selected area, so they can replace it in place
+fn fits_selection(contents: &Level, size: (usize, usize)) -> bool {
+    (contents.width, contents.height()) == size
+}
+
+/// Gets the tiles covered by a shape drawn from one tile to another
+fn shape_tiles(mode: ShapeMode, start: (usize, usize), end: (usize, usize)) -> Vec<(usize, usize)> {
+    let min = (start.0.min(end.0), start.1.min(end.1));
+    let max = (start.0.max(end.0), start.1.max(end.1));
+    let area = (min.1..=max.1).flat_map(|y| (min.0..=max.0).map(move |x| (x, y)));
+    // whether a tile lies within the ellipse fitting the shape's bounds
+    let in_ellipse = |x: isize, y: isize| {
+        let radius = (
+            (max.0 - min.0 + 1) as f32 / 2.0,
+            (max.1 - min.1 + 1) as f32 / 2.0,
+        );
+        let dx = (x as f32 + 0.5 - min.0 as f32 - radius.0) / radius.0;
+        let dy = (y as f32 + 0.5 - min.1 as f32 - radius.1) / radius.1;
+        dx * dx + dy * dy <= 1.0
+    };
+    match mode {
+        ShapeMode::Rect => area.collect(),
+        ShapeMode::HollowRect => area
+            .filter(|(x, y)| *x == min.0 || *x == max.0 || *y == min.1 || *y == max.1)
+            .collect(),
+        ShapeMode::Line => {
+            // bresenham's line algorithm
+            let (mut x, mut y) = (start.0 as isize, start.1 as isize);
+            let (x1, y1) = (end.0 as isize, end.1 as isize);
+            let dx = (x1 - x).abs();
+            let dy = -(y1 - y).abs();
+            let step = ((x1 - x).signum(), (y1 - y).signum());
+            let mut error = dx + dy;
+            let mut tiles = vec![(x as usize, y as usize)];
+            while (x, y) != (x1, y1) {
+                let doubled = error * 2;
+                if doubled >= dy {
+                    error += dy;
+                    x += step.0;
+                }
+                if doubled <= dx {
+                    error += dx;
+                    y += step.1;
+                }
+                tiles.push((x as usize, y as usize));
+            }
+            tiles
+        }
+        ShapeMode::Ellipse => area
+            .filter(|(x, y)| in_ellipse(*x as isize, *y as isize))
+            .collect(),
+        ShapeMode::HollowEllipse => area
+            .filter(|(x, y)| {
+                let (x, y) = (*x as isize, *y as isize);
+                in_ellipse(x, y)
+                    && [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                        .iter()
+                        .any(|(x, y)| !in_ellipse(*x, *y))
+            })
+            .collect(),
+    }
+}
+
+struct ResizeData {
+    width: TextInputData,
+    height: TextInputData,
+    anchor: (u8, u8),
+}
+
+enum MakerMenu {
+    Closed,
+    Paused,
+    Resize(ResizeData),
+    SaveStamp(TextInputData),
+    /// Picking a name to save the level as a new local level
+    SaveAs(TextInputData),
+    /// Asking whether to save or discard changes before exiting
+    ConfirmExit,
+    /// Editing the properties of the character at this index.
+    /// Opened by right clicking an enemy, or clicking it without moving it when placing characters.
+    Inspect(usize),
 }
 
 pub enum MakerUpdateResult {
@@ -27,6 +151,10 @@ pub enum MakerUpdateResult {
     EnterRuntime,
     SaveAndExit,
     ExitNoSave,
+    /// A stamp was saved or deleted, and the stamps should be stored
+    StampsChanged,
+    /// The level changed since it was last autosaved, and should be stored in the recovery slot
+    Autosave,
 }
 
 pub struct GoblinMaker<'a> {
@@ -42,48 +170,50 @@ pub struct GoblinMaker<'a> {
     dragging: Dragging,
     selected_tile: Option<(usize, u8)>,
     tab_tiles: [(&'a Spritesheet, Vec<Vec2>); 3],
-    menu_open: bool,
+    /// Generation of the assets `tab_tiles` and `limits` were built from, see [Assets::generation]
+    tab_tiles_generation: u32,
+    /// What the level may use of the assets, checked before playing it
+    limits: LevelLimits,
+    menu: MakerMenu,
     tool: Tool,
+    shape_mode: ShapeMode,
+    eraser_mode: EraserMode,
+    eraser_size: usize,
+    history: History,
+    /// History state of the level when it was opened,
+    /// or none if it already had unsaved changes (such as when recovered from an autosave)
+    saved_state: Option<usize>,
+    /// History state of the level when it was last autosaved
+    autosave_state: usize,
+    autosave_timer: f32,
+    /// History state of the level when it was last verified
+    verified_state: Option<usize>,
+    /// Top left tile and size of the selected area
+    selection: Option<((usize, usize), (usize, usize))>,
+    /// Contents being moved or pasted, and what tile of it the cursor holds
+    floating: Option<(Level, (isize, isize))>,
+    /// Copied part of a level, with character positions relative to the top left corner
+    pub clipboard: Option<Level>,
+    /// Character being dragged to a new position, and where on it the cursor holds it
+    moving_character: Option<(usize, Vec2)>,
+    /// Problems found in the level, and the history state they were found in
+    warnings: (Option<usize>, Vec<LevelWarning>),
+    pub stamps: Vec<(String, Level)>,
+    /// Names of all local levels, which can't be used when saving as a new level
+    pub level_names: Vec<String>,
+    /// Which of the terrain, obstacle and character layers are hidden
+    hidden_layers: [bool; 3],
+    /// Which of the terrain, obstacle and character layers can't be edited
+    locked_layers: [bool; 3],
 }
 
 fn get_tab_tiles(assets: &Assets) -> [(&Spritesheet, Vec<Vec2>); 3] {
-    fn get_tiles(tab: usize, assets: &Assets) -> (&Spritesheet, Vec<Vec2>) {
-        let texture = if tab == 0 {
-            &assets.terrain_tileset
-        } else if tab == 1 {
-            &assets.obstacles_tileset
-        } else {
-            &assets.character_tileset
-        };
-
-        let image = texture.texture.get_texture_data();
-        let mut tiles = Vec::new();
-        'outer: for y in 0..texture.texture.height() as u32 / 16 {
-            let y = y as f32 * 16.0;
-            for x in 0..texture.texture.width() as u32 / 16 {
-                let x = x as f32 * 16.0;
-                let area = image.sub_image(Rect {
-                    x,
-                    y,
-                    w: 16.0,
-                    h: 16.0,
-                });
-                let mut empty = true;
-                for pixel in area.get_image_data().iter() {
-                    if pixel[3] != 0 {
-                        empty = false;
-                        break;
-                    }
-                }
-                if empty {
-                    break 'outer;
-                }
-                tiles.push(vec2(x / 16.0, y / 16.0));
-            }
-        }
-        (texture, tiles)
-    }
-    std::array::from_fn(|f| get_tiles(f, assets))
+    [
+        assets.terrain_tileset(),
+        assets.obstacles_tileset(),
+        assets.character_tileset(),
+    ]
+    .map(|f| (f, f.get_tiles()))
 }
 
 impl<'a> GoblinMaker<'a> {
@@ -107,6 +237,8 @@ impl<'a> GoblinMaker<'a> {
             verified: false,
             modified: false,
             tab_tiles: get_tab_tiles(assets),
+            tab_tiles_generation: assets.generation(),
+            limits: LevelLimits::new(assets),
             level_renderer,
             assets,
             level,
@@ -115,8 +247,25 @@ impl<'a> GoblinMaker<'a> {
             sidebar: (999.0, 0, 1.0),
             dragging: Dragging::MenuOwned,
             selected_tile: Some((0, 0)),
-            menu_open: false,
+            menu: MakerMenu::Closed,
             tool: Tool::Pencil,
+            shape_mode: ShapeMode::Rect,
+            eraser_mode: EraserMode::Brush,
+            eraser_size: 1,
+            history: History::default(),
+            saved_state: Some(0),
+            autosave_state: 0,
+            autosave_timer: 0.0,
+            verified_state: None,
+            selection: None,
+            floating: None,
+            clipboard: None,
+            moving_character: None,
+            warnings: (None, Vec::new()),
+            stamps: Vec::new(),
+            level_names: Vec::new(),
+            hidden_layers: [false; 3],
+            locked_layers: [false; 3],
         }
     }
     pub fn new(assets: &'a Assets) -> Self {
@@ -144,20 +293,88 @@ impl<'a> GoblinMaker<'a> {
 
         Self::from(assets, level, None)
     }
+    /// Opens the popup asking to save or discard changes before exiting
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn confirm_exit(&mut self) {
+        self.menu = MakerMenu::ConfirmExit;
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn is_confirming_exit(&self) -> bool {
+        matches!(self.menu, MakerMenu::ConfirmExit)
+    }
+    /// Marks the level as having unsaved changes since before it was opened
+    pub fn set_unsaved(&mut self) {
+        self.saved_state = None;
+        self.update_flags();
+    }
+    pub fn set_verified(&mut self) {
+        self.verified = true;
+        self.verified_state = Some(self.history.state());
+    }
+    /// Updates the `modified` and `verified` flags after the history state changed
+    fn update_flags(&mut self) {
+        let state = self.history.state();
+        self.modified = self.saved_state != Some(state);
+        self.verified = self.verified_state == Some(state);
+    }
+    /// Hidden and locked layers are left untouched by all tools
+    fn editable(&self, layer: u8) -> bool {
+        let layer = layer as usize;
+        !self.hidden_layers[layer] && !self.locked_layers[layer]
+    }
+    /// Finds the topmost editable character covering a point in the level
+    fn character_at(&self, pos: Vec2) -> Option<usize> {
+        if !self.editable(2) {
+            return None;
+        }
+        self.level.characters.iter().rposition(|f| {
+            (f.0.0..f.0.0 + 16.0).contains(&pos.x) && (f.0.1..f.0.1 + 16.0).contains(&pos.y)
+        })
+    }
+    /// Where a dragged character would be dropped, and whether that spot is free.
+    /// Snaps to tiles, unless shift is held.
+    fn character_drop(&self, index: usize, pos: Vec2) -> ((f32, f32), bool) {
+        let pos = if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+            pos.round()
+        } else {
+            (pos / 16.0).round() * 16.0
+        };
+        let last = vec2(
+            ((self.level.width - 1) * 16) as f32,
+            ((self.level.height() - 1) * 16) as f32,
+        );
+        let pos = pos.clamp(Vec2::ZERO, last);
+        let free = !self.level.characters.iter().enumerate().any(|(i, f)| {
+            i != index && (f.0.0 - pos.x).abs() < 16.0 && (f.0.1 - pos.y).abs() < 16.0
+        });
+        ((pos.x, pos.y), free)
+    }
+    /// Clears the layers of a tile that can't be edited
+    fn editable_tile(&self, tile: [u8; 2]) -> [u8; 2] {
+        [0, 1].map(|f| {
+            if self.editable(f) {
+                tile[f as usize]
+            } else {
+                0
+            }
+        })
+    }
     fn use_tool(&mut self, tx: usize, ty: usize, tile_index: usize, tab_index: u8) {
-        self.modified = true;
-        self.verified = false;
         match self.tool {
             Tool::Pencil => {
+                if !self.editable(tab_index) {
+                    return;
+                }
                 if tab_index == 2 {
                     // tab index 2 is character tab. place character
                     let pos = ((tx * 16) as f32, (ty * 16) as f32);
                     // check no character is already placed there
                     if !self.level.characters.iter().any(|f| f.0 == pos) {
+                        self.history.modify_characters(&self.level);
                         let character = match tile_index {
                             0 => Character::PlayerSpawn,
                             1 => Character::Flag,
-                            _ => Character::WanderEnemy(tile_index - 2),
+                            _ => Character::wander_enemy(tile_index - 2),
                         };
                         let bundle = (pos, character, tile_index);
                         if tile_index == 0 {
@@ -172,30 +389,243 @@ impl<'a> GoblinMaker<'a> {
                     // general tile placing code
                     let mut tile = self.level.get_tile(tx, ty);
                     tile[tab_index as usize] = tile_index as u8 + 1;
-                    self.level_renderer.set_tile(&mut self.level, tx, ty, tile);
+                    self.history
+                        .set_tile(&mut self.level, &mut self.level_renderer, tx, ty, tile);
                 }
             }
             Tool::Eraser => {
                 let Dragging::WorldOwned(layer, _) = self.dragging else {
                     panic!()
                 };
-                if layer == 2 {
-                    let pos = ((tx * 16) as f32, (ty * 16) as f32);
-
-                    self.level.characters.retain(|f| {
-                        matches!(f.1, Character::PlayerSpawn)
-                            || matches!(f.1, Character::Flag)
-                            || f.0 != pos
-                    });
-                } else {
-                    // general tile placing code
-                    let mut tile = self.level.get_tile(tx, ty);
-                    tile[layer as usize] = 0;
-                    self.level_renderer.set_tile(&mut self.level, tx, ty, tile);
+                if self.eraser_mode == EraserMode::Brush {
+                    let (start, end) = self.brush_area(tx, ty);
+                    let mut layers = [false; 3];
+                    layers[layer as usize] = true;
+                    self.erase_area(start, end, layers);
+                }
+            }
+            Tool::Shape | Tool::Fill | Tool::Select => {}
+        }
+    }
+    /// Gets the area covered by the eraser brush, centered on a tile.
+    /// Returns the top left tile and the (exclusive) bottom right tile.
+    fn brush_area(&self, tx: usize, ty: usize) -> ((usize, usize), (usize, usize)) {
+        let offset = (self.eraser_size - 1) / 2;
+        let start = (tx.saturating_sub(offset), ty.saturating_sub(offset));
+        let end = (
+            (tx - offset.min(tx) + self.eraser_size).min(self.level.width),
+            (ty - offset.min(ty) + self.eraser_size).min(self.level.height()),
+        );
+        (start, end)
+    }
+    /// Erases the chosen layers (terrain, obstacles and characters) in an area.
+    /// Player spawn and flag can't be removed, only moved.
+    fn erase_area(&mut self, start: (usize, usize), end: (usize, usize), layers: [bool; 3]) {
+        let layers = [0, 1, 2].map(|f| layers[f as usize] && self.editable(f));
+        if layers[0] || layers[1] {
+            let mut changes = Vec::new();
+            for y in start.1..end.1.min(self.level.height()) {
+                for x in start.0..end.0.min(self.level.width) {
+                    let mut tile = self.level.get_tile(x, y);
+                    for layer in 0..2 {
+                        if layers[layer] {
+                            tile[layer] = 0;
+                        }
+                    }
+                    changes.push((x, y, tile));
+                }
+            }
+            self.history
+                .set_tiles(&mut self.level, &mut self.level_renderer, changes);
+        }
+        if layers[2] {
+            let (start, end) = (
+                vec2(start.0 as f32, start.1 as f32) * 16.0,
+                vec2(end.0 as f32, end.1 as f32) * 16.0,
+            );
+            self.history.modify_characters(&self.level);
+            self.level.characters.retain(|f| {
+                matches!(f.1, Character::PlayerSpawn)
+                    || matches!(f.1, Character::Flag)
+                    || f.0.0 < start.x
+                    || f.0.1 < start.y
+                    || f.0.0 >= end.x
+                    || f.0.1 >= end.y
+            });
+        }
+    }
+    /// Flood fills the area of equal tiles around a tile on one layer.
+    /// Does nothing if the area is larger than [MAX_FILL_TILES].
+    fn fill(&mut self, x: usize, y: usize, tile_index: usize, layer: u8) {
+        if !self.editable(layer) {
+            return;
+        }
+        let layer = layer as usize;
+        let target = self.level.get_tile(x, y)[layer];
+        let new = tile_index as u8 + 1;
+        if target == new {
+            return;
+        }
+        let (width, height) = (self.level.width, self.level.height());
+        let mut visited = vec![false; width * height];
+        visited[x + y * width] = true;
+        let mut stack = vec![(x, y)];
+        let mut area = Vec::new();
+        while let Some((x, y)) = stack.pop() {
+            area.push((x, y));
+            if area.len() > MAX_FILL_TILES {
+                return;
+            }
+            for (x, y) in [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ] {
+                if x < width
+                    && y < height
+                    && !visited[x + y * width]
+                    && self.level.tiles[x + y * width][layer] == target
+                {
+                    visited[x + y * width] = true;
+                    stack.push((x, y));
                 }
             }
-            Tool::Shape => {}
         }
+        let changes = area
+            .into_iter()
+            .map(|(x, y)| {
+                let mut tile = self.level.get_tile(x, y);
+                tile[layer] = new;
+                (x, y, tile)
+            })
+            .collect();
+        self.history
+            .set_tiles(&mut self.level, &mut self.level_renderer, changes);
+    }
+    fn in_selection(&self, pos: (f32, f32)) -> bool {
+        let Some(((x, y), (width, height))) = self.selection else {
+            return false;
+        };
+        let (tx, ty) = ((pos.0 / 16.0).floor(), (pos.1 / 16.0).floor());
+        tx >= x as f32 && ty >= y as f32 && tx < (x + width) as f32 && ty < (y + height) as f32
+    }
+    fn copy_selection(&self) -> Option<Level> {
+        let ((x, y), (width, height)) = self.selection?;
+        let mut tiles = Vec::with_capacity(width * height);
+        for ty in y..y + height {
+            for tx in x..x + width {
+                tiles.push(self.editable_tile(self.level.get_tile(tx, ty)));
+            }
+        }
+        let origin = ((x * 16) as f32, (y * 16) as f32);
+        let characters = self
+            .level
+            .characters
+            .iter()
+            .filter(|f| self.editable(2) && self.in_selection(f.0))
+            .map(|(pos, character, index)| {
+                (
+                    (pos.0 - origin.0, pos.1 - origin.1),
+                    character.clone(),
+                    *index,
+                )
+            })
+            .collect();
+        Some(Level {
+            width,
+            tiles,
+            characters,
+        })
+    }
+    fn erase_selection(&mut self) {
+        let Some(((x, y), (width, height))) = self.selection else {
+            return;
+        };
+        self.erase_area((x, y), (x + width, y + height), [true; 3]);
+    }
+    /// Flips or rotates the contents being moved or pasted, or otherwise the selected area in place.
+    ///
+    /// Contents that no longer fit the selected area (like a rotated non-square selection) would cover
+    /// tiles outside of it, so they're picked up to be placed again instead.
+    fn transform_selection(&mut self, transform: impl Fn(&mut Level)) {
+        if let Some((floating, _)) = &mut self.floating {
+            transform(floating);
+            // contents picked up from the selection are left as they were if dropped where they came from,
+            // so move them out of it right away
+            if self.selection.is_some() {
+                self.erase_selection();
+                self.selection = None;
+            }
+            return;
+        }
+        let Some(mut region) = self.copy_selection() else {
+            return;
+        };
+        let ((x, y), size) = self.selection.unwrap();
+        transform(&mut region);
+        self.erase_selection();
+        if fits_selection(&region, size) {
+            self.place(&region, x as isize, y as isize);
+        } else {
+            self.selection = None;
+            self.floating = Some((region, (0, 0)));
+        }
+    }
+    /// Places clipboard contents with its top left corner at a tile. Empty tiles are left as is.
+    fn place(&mut self, clipboard: &Level, x: isize, y: isize) {
+        let (width, height) = (self.level.width as isize, self.level.height() as isize);
+        for (index, tile) in clipboard.tiles.iter().enumerate() {
+            let tx = x + (index % clipboard.width) as isize;
+            let ty = y + (index / clipboard.width) as isize;
+            if tx < 0 || ty < 0 || tx >= width || ty >= height {
+                continue;
+            }
+            let (tx, ty) = (tx as usize, ty as usize);
+            let mut new = self.level.get_tile(tx, ty);
+            for layer in 0..2 {
+                if tile[layer] != 0 && self.editable(layer as u8) {
+                    new[layer] = tile[layer];
+                }
+            }
+            self.history
+                .set_tile(&mut self.level, &mut self.level_renderer, tx, ty, new);
+        }
+        if !self.editable(2) {
+            return;
+        }
+        self.history.modify_characters(&self.level);
+        for (pos, character, index) in clipboard.characters.iter() {
+            let pos = (pos.0 + (x * 16) as f32, pos.1 + (y * 16) as f32);
+            if pos.0 < 0.0
+                || pos.1 < 0.0
+                || pos.0 >= (width * 16) as f32
+                || pos.1 >= (height * 16) as f32
+                || self.level.characters.iter().any(|f| f.0 == pos)
+            {
+                continue;
+            }
+            let bundle = (pos, character.clone(), *index);
+            match character {
+                Character::PlayerSpawn => self.level.characters[0] = bundle,
+                Character::Flag => self.level.characters[1] = bundle,
+                _ => self.level.characters.push(bundle),
+            }
+        }
+    }
+    fn resize_level(&mut self, width: usize, height: usize, anchor: (u8, u8)) {
+        if width == self.level.width && height == self.level.height() {
+            return;
+        }
+        let mut resized = self.level.clone();
+        let (dx, dy) = resized.resize(width, height, anchor);
+        self.history
+            .replace_level(&mut self.level, &mut self.level_renderer, resized);
+        if self.history.commit(&self.level) {
+            self.update_flags();
+        }
+        // keep the view over the same tiles as before
+        self.camera_pos += vec2(dx as f32, dy as f32) * 16.0;
     }
     pub fn update(&mut self) -> MakerUpdateResult {
         let delta_time = get_frame_time();
@@ -209,6 +639,7 @@ impl<'a> GoblinMaker<'a> {
             (((mouse_x) / self.camera_zoom + self.camera_pos.x) / 16.0).floor(),
             (((mouse_y) / self.camera_zoom + self.camera_pos.y) / 16.0).floor(),
         );
+        let mouse_world = vec2(mouse_x, mouse_y) / self.camera_zoom + self.camera_pos;
         let cursor_tile = if mouse_tile_x >= 0.0
             && mouse_tile_y >= 0.0
             && mouse_tile_x < self.level.width as f32
@@ -221,7 +652,7 @@ impl<'a> GoblinMaker<'a> {
         let last_dragging = self.dragging;
         if !is_mouse_button_down(MouseButton::Left) {
             self.dragging = Dragging::No;
-            if self.menu_open {
+            if !matches!(self.menu, MakerMenu::Closed) {
                 self.dragging = Dragging::UiOwned;
             }
         }
@@ -230,7 +661,7 @@ impl<'a> GoblinMaker<'a> {
         let clicking = is_mouse_button_pressed(MouseButton::Left);
 
         self.sidebar.0 = (self.sidebar.0 + delta_time * self.sidebar.2 * 12.0).clamp(-0.0, 1.0);
-        let sidebar_size = vec2(60.0, 275.0);
+        let sidebar_size = vec2(79.0, 275.0);
 
         let topbar = UIRect::new(
             vec2(1.0, 2.0) * scale_factor,
@@ -251,30 +682,96 @@ impl<'a> GoblinMaker<'a> {
         );
         let play_btn = UIImageButton::new(
             vec2(
-                (actual_screen_width - self.assets.play_btn.frames[0].0.width() * scale_factor)
+                (actual_screen_width
+                    - self.assets.animation(ids::PLAY_BTN).frames[0].0.width() * scale_factor)
                     / 2.0,
                 1.0 * scale_factor,
             ),
-            &self.assets.play_btn.frames[0].0,
-            &self.assets.play_btn.frames[1].0,
+            &self.assets.animation(ids::PLAY_BTN).frames[0].0,
+            &self.assets.animation(ids::PLAY_BTN).frames[1].0,
             scale_factor,
             false,
         );
         let handle_texture = if self.sidebar.2 < 0.0 {
             (
-                &self.assets.handle_btn.frames[0].0,
-                &self.assets.handle_btn.frames[1].0,
+                &self.assets.animation(ids::HANDLE_BTN).frames[0].0,
+                &self.assets.animation(ids::HANDLE_BTN).frames[1].0,
             )
         } else {
             (
-                &self.assets.handle_btn.frames[2].0,
-                &self.assets.handle_btn.frames[3].0,
+                &self.assets.animation(ids::HANDLE_BTN).frames[2].0,
+                &self.assets.animation(ids::HANDLE_BTN).frames[3].0,
             )
         };
 
-        let tab = &self.tab_tiles[self.sidebar.1 as usize];
+        let mut result = MakerUpdateResult::None;
         let mut tile_btns = Vec::new();
+        let mut stamp_btns = Vec::new();
+        if self.sidebar.1 == 3 {
+            // stamps tab lists the saved stamps, with a button for saving the selection as a new one
+            let font_size = (8.0 * scale_factor) as u16;
+            let row_size = vec2(56.0, 16.0);
+            let save_btn = UITextButton::new(
+                (sidebar_pos + vec2(3.0, 25.0)) * scale_factor,
+                vec2(row_size.x + 17.0, row_size.y) * scale_factor,
+                "Save selection".to_string(),
+                if self.selection.is_some() {
+                    SKY_COLOR
+                } else {
+                    MAKER_BG_COLOR
+                },
+                MAKER_BG_COLOR,
+                (scale_factor, BLACK),
+                (font_size, self.assets.font(), 3.0 * scale_factor),
+            );
+            if save_btn.is_hovered() && clicking && self.selection.is_some() {
+                self.menu = MakerMenu::SaveStamp(TextInputData::default());
+            }
+            stamp_btns.push((save_btn, None));
+            let mut deleted = None;
+            for (index, (name, stamp)) in self.stamps.iter().enumerate() {
+                let pos = sidebar_pos + vec2(3.0, 25.0 + (index + 1) as f32 * (row_size.y + 2.0));
+                let btn = UITextButton::new(
+                    pos * scale_factor,
+                    row_size * scale_factor,
+                    name.clone(),
+                    SKY_COLOR,
+                    MAKER_BG_COLOR,
+                    (scale_factor, BLACK),
+                    (font_size, self.assets.font(), 3.0 * scale_factor),
+                );
+                let delete_btn = UIImageButton::new(
+                    (pos + vec2(row_size.x, 0.0)) * scale_factor,
+                    &self.assets.animation(ids::DELETE_BTN).frames[0].0,
+                    &self.assets.animation(ids::DELETE_BTN).frames[1].0,
+                    scale_factor,
+                    false,
+                );
+                if clicking && delete_btn.is_hovered() {
+                    deleted = Some(index);
+                } else if clicking && btn.is_hovered() {
+                    // place stamps like pasting
+                    self.tool = Tool::Select;
+                    self.selection = None;
+                    self.floating = Some((stamp.clone(), (0, 0)));
+                }
+                stamp_btns.push((btn, Some(delete_btn)));
+            }
+            if let Some(index) = deleted {
+                self.stamps.remove(index);
+                result = MakerUpdateResult::StampsChanged;
+            }
+        }
+        if self.tab_tiles_generation != self.assets.generation() {
+            self.tab_tiles = get_tab_tiles(self.assets);
+            self.limits = LevelLimits::new(self.assets);
+            self.tab_tiles_generation = self.assets.generation();
+        }
+        let tab = &self.tab_tiles[self.sidebar.1.min(2) as usize];
         for (index, tile) in tab.1.iter().enumerate() {
+            if self.sidebar.1 == 3 {
+                break;
+            }
             let selected = if let Some(selected) = &self.selected_tile
                 && selected.0 == index
                 && selected.1 == self.sidebar.1
@@ -285,7 +782,7 @@ impl<'a> GoblinMaker<'a> {
             };
 
             let button = UITileButton::new(
-                (vec2((index % 3) as f32 * 19.0, (index / 3) as f32 * 19.0)
+                (vec2((index % 4) as f32 * 19.0, (index / 4) as f32 * 19.0)
                     + sidebar_pos
                     + vec2(3.0, 25.0))
                     * scale_factor,
@@ -305,20 +802,35 @@ impl<'a> GoblinMaker<'a> {
 
         let pause_btn = UIImageButton::new(
             (vec2(1.0, 2.0)) * scale_factor,
-            &self.assets.pause_btn_topbar.frames[0].0,
-            &self.assets.pause_btn_topbar.frames[1].0,
+            &self.assets.animation(ids::PAUSE_BTN_TOPBAR).frames[0].0,
+            &self.assets.animation(ids::PAUSE_BTN_TOPBAR).frames[1].0,
             scale_factor,
             false,
         );
-        let mut result = MakerUpdateResult::None;
-        if is_key_pressed(KeyCode::Escape) || (pause_btn.is_hovered() && clicking) {
-            self.menu_open = true;
+        if is_key_pressed(KeyCode::Escape)
+            && matches!(self.menu, MakerMenu::Closed)
+            && (self.selection.is_some() || self.floating.is_some())
+        {
+            self.selection = None;
+            self.floating = None;
+        } else if is_key_pressed(KeyCode::Escape) || (pause_btn.is_hovered() && clicking) {
+            self.menu = MakerMenu::Paused;
         }
+        // don't let hotkeys trigger while typing in a text field
+        let typing = matches!(
+            self.menu,
+            MakerMenu::Resize(_) | MakerMenu::SaveStamp(_) | MakerMenu::SaveAs(_)
+        );
 
-        for (index, (tool, animation)) in
-            all::<Tool>().zip(self.assets.tool_btns.iter()).enumerate()
-        {
+        let tool_count = TOOL_BTNS.len();
+        for (index, (tool, id)) in all::<Tool>().zip(TOOL_BTNS).enumerate() {
             let active = self.tool == tool;
+            // tools with modes show the current mode
+            let animation = self.assets.animation(match tool {
+                Tool::Shape => SHAPE_MODE_BTNS[self.shape_mode as usize],
+                Tool::Eraser => ERASER_MODE_BTNS[self.eraser_mode as usize],
+                _ => id,
+            });
             let t = if active {
                 &animation.frames[0].0
             } else {
@@ -336,18 +848,114 @@ impl<'a> GoblinMaker<'a> {
             }
             tool_btns.push(btn);
         }
-        if is_key_pressed(KeyCode::E) {
+        // popout for picking the mode of the current tool
+        let popout = match self.tool {
+            Tool::Shape => Some((self.shape_mode as usize, &SHAPE_MODE_BTNS[..])),
+            Tool::Eraser => Some((self.eraser_mode as usize, &ERASER_MODE_BTNS[..])),
+            _ => None,
+        };
+        let mut picked_mode = None;
+        if let Some((current, ids)) = popout {
+            for (index, id) in ids.iter().enumerate() {
+                let animation = self.assets.animation(id);
+                let active = current == index;
+                let t = if active {
+                    &animation.frames[0].0
+                } else {
+                    &animation.frames[1].0
+                };
+                let btn = UIImageButton::new(
+                    (vec2(31.0, 2.0) + button_offset * (tool_count + index) as f32) * scale_factor,
+                    t,
+                    t,
+                    scale_factor,
+                    active,
+                );
+                if btn.is_hovered() && clicking {
+                    picked_mode = Some(index);
+                }
+                tool_btns.push(btn);
+            }
+        }
+        // flipping and rotating the selection
+        let mut transform = None;
+        if self.tool == Tool::Select {
+            for (index, id) in TRANSFORM_BTNS.into_iter().enumerate() {
+                let animation = self.assets.animation(id);
+                let t = &animation.frames[1].0;
+                let btn = UIImageButton::new(
+                    (vec2(31.0, 2.0) + button_offset * (tool_count + index) as f32) * scale_factor,
+                    t,
+                    &animation.frames[0].0,
+                    scale_factor,
+                    false,
+                );
+                if btn.is_hovered() && clicking {
+                    transform = Some(index);
+                }
+                tool_btns.push(btn);
+            }
+        }
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        if !typing && !ctrl && self.tool == Tool::Select {
+            if is_key_pressed(KeyCode::H) {
+                transform = Some(0);
+            } else if is_key_pressed(KeyCode::V) {
+                transform = Some(1);
+            } else if is_key_pressed(KeyCode::T) {
+                transform = Some(2);
+            }
+        }
+        // selection can't change in the middle of dragging it out
+        if !matches!(self.dragging, Dragging::WorldOwned(_, _)) || self.floating.is_some() {
+            match transform {
+                Some(0) => self.transform_selection(|f| f.flip(true)),
+                Some(1) => self.transform_selection(|f| f.flip(false)),
+                Some(2) => self.transform_selection(Level::rotate),
+                _ => {}
+            }
+        }
+        if let Some(index) = picked_mode {
+            match self.tool {
+                Tool::Shape => self.shape_mode = all::<ShapeMode>().nth(index).unwrap(),
+                Tool::Eraser => self.eraser_mode = all::<EraserMode>().nth(index).unwrap(),
+                _ => {}
+            }
+        }
+        if !typing && self.tool == Tool::Eraser {
+            if is_key_pressed(KeyCode::LeftBracket) {
+                self.eraser_size = (self.eraser_size - 1).max(1);
+            } else if is_key_pressed(KeyCode::RightBracket) {
+                self.eraser_size = (self.eraser_size + 1).min(MAX_ERASER_SIZE);
+            }
+        }
+        if !typing && is_key_pressed(KeyCode::E) {
             self.tool = Tool::Eraser;
         }
-        if is_key_pressed(KeyCode::B) || is_key_pressed(KeyCode::P) {
+        if !typing && (is_key_pressed(KeyCode::B) || is_key_pressed(KeyCode::P)) {
             self.tool = Tool::Pencil;
         }
-        if is_key_pressed(KeyCode::F) || is_key_pressed(KeyCode::G) || is_key_pressed(KeyCode::S) {
+        if !typing
+            && (is_key_pressed(KeyCode::F)
+                || is_key_pressed(KeyCode::G)
+                || is_key_pressed(KeyCode::S))
+        {
             self.tool = Tool::Shape;
         }
+        if !typing && is_key_pressed(KeyCode::M) {
+            self.tool = Tool::Select;
+        }
+        if self.tool != Tool::Select {
+            self.selection = None;
+            self.floating = None;
+        }
 
-        if (clicking && play_btn.is_hovered()) || is_key_pressed(KeyCode::R) {
-            result = MakerUpdateResult::EnterRuntime;
+        if (clicking && play_btn.is_hovered()) || (!typing && is_key_pressed(KeyCode::R)) {
+            // reloaded assets might not have every tile or enemy the level uses anymore
+            match self.level.validate(&self.limits) {
+                Ok(()) => result = MakerUpdateResult::EnterRuntime,
+                Err(errors) => warn!("can't play the level:\n{}", describe_errors(&errors)),
+            }
         }
         let handle_btn = UIImageButton::new(
             (sidebar_pos + vec2(sidebar_size.x + 2.0, (sidebar_size.y - 9.0) / 2.0)) * scale_factor,
@@ -364,9 +972,10 @@ impl<'a> GoblinMaker<'a> {
 
         let mut tab_btns = Vec::new();
         for (i, t) in [
-            &self.assets.tile_btn.frames,
-            &self.assets.obstacles_btn.frames,
-            &self.assets.character_btn.frames,
+            &self.assets.animation(ids::TILE_BTN).frames,
+            &self.assets.animation(ids::OBSTACLES_BTN).frames,
+            &self.assets.animation(ids::CHARACTER_BTN).frames,
+            &self.assets.animation(ids::STAMPS_BTN).frames,
         ]
         .iter()
         .enumerate()
@@ -383,13 +992,55 @@ impl<'a> GoblinMaker<'a> {
             }
             tab_btns.push(btn);
         }
+        // show/hide and lock toggles below each layer's tab
+        let mut toggled_visibility = false;
+        for i in 0..3 {
+            let pos = sidebar_pos + vec2(3.0, 17.0) + button_offset * i as f32;
+            for (offset, animation, state) in [
+                (
+                    0.0,
+                    self.assets.animation(ids::EYE_BTN),
+                    &mut self.hidden_layers[i],
+                ),
+                (
+                    9.0,
+                    self.assets.animation(ids::LOCK_BTN),
+                    &mut self.locked_layers[i],
+                ),
+            ] {
+                let t = &animation.frames[*state as usize].0;
+                let btn = UIImageButton::new(
+                    (pos + vec2(offset, 0.0)) * scale_factor,
+                    t,
+                    t,
+                    scale_factor,
+                    false,
+                );
+                if btn.is_hovered() && clicking {
+                    *state = !*state;
+                    toggled_visibility |= offset == 0.0;
+                }
+                tab_btns.push(btn);
+            }
+        }
+        if toggled_visibility {
+            let visible_layers = [!self.hidden_layers[0], !self.hidden_layers[1]];
+            if self.level_renderer.visible_layers != visible_layers {
+                self.level_renderer.visible_layers = visible_layers;
+                self.level_renderer.rebuild(&self.level);
+            }
+        }
 
-        if is_key_pressed(KeyCode::Key1) {
-            self.sidebar.1 = 0
-        } else if is_key_pressed(KeyCode::Key2) {
-            self.sidebar.1 = 1
-        } else if is_key_pressed(KeyCode::Key3) {
-            self.sidebar.1 = 2;
+        if !typing {
+            if is_key_pressed(KeyCode::Key1) {
+                self.sidebar.1 = 0
+            } else if is_key_pressed(KeyCode::Key2) {
+                self.sidebar.1 = 1
+            } else if is_key_pressed(KeyCode::Key3) {
+                self.sidebar.1 = 2;
+            } else if is_key_pressed(KeyCode::Key4) {
+                self.sidebar.1 = 3;
+            }
         }
 
         let ui_hovered = topbar.is_hovered()
@@ -400,24 +1051,22 @@ impl<'a> GoblinMaker<'a> {
             || tab_btns.iter().any(|f| f.is_hovered())
             || tool_btns.iter().any(|f| f.is_hovered())
             || tile_btns.iter().any(|f| f.is_hovered());
-        if (ui_hovered && clicking) || self.menu_open {
+        if (ui_hovered && clicking) || !matches!(self.menu, MakerMenu::Closed) {
             self.dragging = Dragging::UiOwned;
         } else {
             // find what layer it is we are clicking.
+            // hidden and locked layers are treated as empty
 
-            let tile = cursor_tile.map(|(tx, ty)| self.level.get_tile(tx, ty));
-            let character = self
-                .level
-                .characters
-                .iter()
-                .find(|f| f.0 == (mouse_tile_x * 16.0, mouse_tile_y * 16.0));
+            let tile = cursor_tile.map(|(tx, ty)| self.editable_tile(self.level.get_tile(tx, ty)));
+            let character_index = self.character_at(mouse_world);
+            let character = character_index.map(|f| &self.level.characters[f]);
             let layer = if self.sidebar.1 == 2 && character.is_some() {
                 2
             } else if let Some(tile) = tile {
                 if (tile[0] == 0 && tile[1] == 0) || (tile[1] != 0 && tile[0] != 0) {
                     if character.is_some() && (tile[0] == 0 && tile[1] == 0) {
                         2
-                    } else if self.sidebar.1 == 2 {
+                    } else if self.sidebar.1 >= 2 {
                         0
                     } else {
                         self.sidebar.1
@@ -430,7 +1079,7 @@ impl<'a> GoblinMaker<'a> {
             } else if character.is_some() {
                 2
             } else {
-                self.sidebar.1
+                self.sidebar.1.min(2)
             };
             if clicking {
                 let start = (
@@ -446,6 +1095,13 @@ impl<'a> GoblinMaker<'a> {
                         character.unwrap().2
                     };
                     self.selected_tile = Some((index, layer));
+                    // right clicking an enemy also opens its properties, whichever tool is selected
+                    if layer == 2
+                        && let Some(character_index) = character_index
+                        && let Character::WanderEnemy { .. } = self.level.characters[character_index].1
+                    {
+                        self.menu = MakerMenu::Inspect(character_index);
+                    }
                 }
         }
 
@@ -501,8 +1157,36 @@ impl<'a> GoblinMaker<'a> {
                 old_mouse_world_y + SCREEN_HEIGHT / 2.0 - scroll_origin.y / self.camera_zoom;
         }
 
+        let pressed = allow_world_mouse && !matches!(last_dragging, Dragging::WorldOwned(_, _));
+        // grabbing placed characters with the pencil on the character tab
+        if let Tool::Pencil = self.tool
+            && pressed
+            && let Dragging::WorldOwned(2, _) = self.dragging
+            && self.selected_tile.is_some_and(|f| f.1 == 2)
+            && let Some(index) = self.character_at(mouse_world)
+        {
+            let pos = self.level.characters[index].0;
+            self.moving_character = Some((index, mouse_world - vec2(pos.0, pos.1)));
+        }
+        if let Some((index, offset)) = self.moving_character
+            && !is_mouse_button_down(MouseButton::Left)
+        {
+            self.moving_character = None;
+            let (pos, free) = self.character_drop(index, mouse_world - offset);
+            if pos == self.level.characters[index].0 {
+                // clicking an enemy without moving it opens its properties
+                if let Character::WanderEnemy { .. } = self.level.characters[index].1 {
+                    self.menu = MakerMenu::Inspect(index);
+                }
+            } else if free {
+                self.history.modify_characters(&self.level);
+                self.level.characters[index].0 = pos;
+            }
+        }
+
         // handle clicking
         if allow_world_mouse
+            && self.moving_character.is_none()
             && let Some((tx, ty)) = cursor_tile
             && let Some((tile_index, tab_index)) = self.selected_tile
             && is_mouse_button_down(MouseButton::Left)
@@ -510,27 +1194,136 @@ impl<'a> GoblinMaker<'a> {
             self.use_tool(tx, ty, tile_index, tab_index);
         }
 
+        if let Tool::Eraser = self.tool
+            && self.eraser_mode != EraserMode::Brush
+            && is_mouse_button_released(MouseButton::Left)
+            && let Dragging::WorldOwned(_, start) = last_dragging
+        {
+            let end = (
+                (mouse_tile_x.max(0.0) as usize).min(self.level.width - 1),
+                (mouse_tile_y.max(0.0) as usize).min(self.level.height() - 1),
+            );
+            // stamps tab isn't a layer, so erase everything there
+            let layers = if self.eraser_mode == EraserMode::AreaAll || self.sidebar.1 == 3 {
+                [true; 3]
+            } else {
+                let mut layers = [false; 3];
+                layers[self.sidebar.1 as usize] = true;
+                layers
+            };
+            self.erase_area(
+                (start.0.min(end.0), start.1.min(end.1)),
+                (start.0.max(end.0) + 1, start.1.max(end.1) + 1),
+                layers,
+            );
+        }
+        if let Tool::Fill = self.tool
+            && pressed
+            && let Some((tx, ty)) = cursor_tile
+            && let Some((tile_index, tab_index)) = self.selected_tile
+            && tab_index < 2
+        {
+            self.fill(tx, ty, tile_index, tab_index);
+        }
+
+        // handle selecting and moving
+        let mouse_tile = (mouse_tile_x as isize, mouse_tile_y as isize);
+        if let Tool::Select = self.tool {
+            if pressed
+                && self.floating.is_none()
+                && let Some(((x, y), _)) = self.selection
+                && self.in_selection((mouse_tile_x * 16.0, mouse_tile_y * 16.0))
+            {
+                // pick up the selection to move it
+                let clipboard = self.copy_selection().unwrap();
+                self.floating = Some((
+                    clipboard,
+                    (mouse_tile.0 - x as isize, mouse_tile.1 - y as isize),
+                ));
+            }
+            if is_mouse_button_released(MouseButton::Left)
+                && let Dragging::WorldOwned(_, start) = last_dragging
+            {
+                if let Some((clipboard, offset)) = self.floating.take() {
+                    let (x, y) = (mouse_tile.0 - offset.0, mouse_tile.1 - offset.1);
+                    // when moving, the selection is the area it was moved from
+                    if self
+                        .selection
+                        .is_none_or(|f| (f.0.0 as isize, f.0.1 as isize) != (x, y))
+                    {
+                        self.erase_selection();
+                        self.place(&clipboard, x, y);
+                    }
+                    let start = (x.max(0) as usize, y.max(0) as usize);
+                    let end = (
+                        (x + clipboard.width as isize).clamp(0, self.level.width as isize) as usize,
+                        (y + clipboard.height() as isize).clamp(0, self.level.height() as isize)
+                            as usize,
+                    );
+                    self.selection = (end.0 > start.0 && end.1 > start.1)
+                        .then_some((start, (end.0 - start.0, end.1 - start.1)));
+                } else {
+                    let end = (
+                        (mouse_tile_x.max(0.0) as usize).min(self.level.width - 1),
+                        (mouse_tile_y.max(0.0) as usize).min(self.level.height() - 1),
+                    );
+                    self.selection = Some((
+                        (start.0.min(end.0), start.1.min(end.1)),
+                        (end.0.abs_diff(start.0) + 1, end.1.abs_diff(start.1) + 1),
+                    ));
+                }
+            }
+        }
+
+        // undo/redo and clipboard, but not in the middle of a stroke
+        if !typing
+            && !allow_world_mouse
+            && (is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl))
+        {
+            let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+            if is_key_pressed(KeyCode::C) || is_key_pressed(KeyCode::X) {
+                if let Some(clipboard) = self.copy_selection() {
+                    self.clipboard = Some(clipboard);
+                }
+                if is_key_pressed(KeyCode::X) {
+                    self.erase_selection();
+                }
+            }
+            if is_key_pressed(KeyCode::V)
+                && let Some(clipboard) = &self.clipboard
+            {
+                self.tool = Tool::Select;
+                self.selection = None;
+                self.floating = Some((clipboard.clone(), (0, 0)));
+            }
+            let changed = if is_key_pressed(KeyCode::Y) || (shift && is_key_pressed(KeyCode::Z)) {
+                self.history.redo(&mut self.level, &mut self.level_renderer)
+            } else if is_key_pressed(KeyCode::Z) {
+                self.history.undo(&mut self.level, &mut self.level_renderer)
+            } else {
+                false
+            };
+            if changed {
+                self.update_flags();
+            }
+        }
+
+        // area of the level that is on screen, in pixels
+        let view = Rect::new(
+            self.camera_pos.x,
+            self.camera_pos.y,
+            actual_screen_width / scale_factor / self.camera_zoom,
+            actual_screen_height / scale_factor / self.camera_zoom,
+        );
+        self.level_renderer
+            .update_chunks(&self.level, view, scale_factor * self.camera_zoom);
         set_default_camera();
         clear_background(MAKER_BG_COLOR);
 
-        draw_texture_ex(
-            &self
-                .level_renderer
-                .camera
-                .render_target
-                .as_ref()
-                .unwrap()
-                .texture,
-            -self.camera_pos.x * scale_factor * self.camera_zoom,
-            -self.camera_pos.y * scale_factor * self.camera_zoom,
-            WHITE,
-            DrawTextureParams {
-                dest_size: Some(Vec2::new(
-                    self.level_renderer.size.x * scale_factor * self.camera_zoom,
-                    self.level_renderer.size.y * scale_factor * self.camera_zoom,
-                )),
-                ..Default::default()
-            },
+        self.level_renderer.draw(
+            view,
+            -self.camera_pos * scale_factor * self.camera_zoom,
+            scale_factor * self.camera_zoom,
         );
         let params = DrawTextureParams {
             dest_size: Some(vec2(
@@ -539,8 +1332,22 @@ impl<'a> GoblinMaker<'a> {
             )),
             ..Default::default()
         };
-        for (pos, _, index) in self.level.characters.iter() {
-            self.assets.character_tileset.draw_tile(
+        for (pos, character, index) in self.level.characters.iter() {
+            if self.hidden_layers[2] {
+                break;
+            }
+            // enemy sprites face left
+            let params = DrawTextureParams {
+                flip_x: matches!(
+                    character,
+                    Character::WanderEnemy {
+                        moving_left: false,
+                        ..
+                    }
+                ),
+                ..params.clone()
+            };
+            self.assets.character_tileset().draw_tile(
                 (pos.0) * scale_factor * self.camera_zoom
                     - self.camera_pos.x * scale_factor * self.camera_zoom,
                 (pos.1) * scale_factor * self.camera_zoom
@@ -550,11 +1357,52 @@ impl<'a> GoblinMaker<'a> {
                 Some(&params),
             );
         }
+        // mark the tiles of problems in the level
+        for warning in self.warnings.1.iter() {
+            let (x, y) = warning.tile();
+            draw_texture_ex(
+                self.assets.texture(ids::WARNING),
+                ((x * 16 + 8) as f32 - self.camera_pos.x) * scale_factor * self.camera_zoom,
+                ((y * 16) as f32 - self.camera_pos.y) * scale_factor * self.camera_zoom,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(8.0, 8.0) * scale_factor * self.camera_zoom),
+                    ..Default::default()
+                },
+            );
+        }
+        // preview where the dragged character lands, in red if the spot is taken
+        if let Some((index, offset)) = self.moving_character {
+            let (pos, free) = self.character_drop(index, mouse_world - offset);
+            let (_, character, sprite) = &self.level.characters[index];
+            let mut params = params.clone();
+            params.flip_x = matches!(
+                character,
+                Character::WanderEnemy {
+                    moving_left: false,
+                    ..
+                }
+            );
+            params.source = Some(Rect {
+                x: (sprite % 3) as f32 * 16.0,
+                y: (sprite / 3) as f32 * 16.0,
+                w: 16.0,
+                h: 16.0,
+            });
+            draw_texture_ex(
+                &self.assets.character_tileset().texture,
+                (pos.0 - self.camera_pos.x) * scale_factor * self.camera_zoom,
+                (pos.1 - self.camera_pos.y) * scale_factor * self.camera_zoom,
+                if free { WHITE } else { RED }.with_alpha(0.75),
+                params,
+            );
+        }
         let released = is_mouse_button_released(MouseButton::Left);
         if let Tool::Shape = &self.tool
             && let Some(selection) = self.selected_tile
             && let Dragging::WorldOwned(_, start) = last_dragging
             && selection.1 < 2
+            && self.editable(selection.1)
         {
             let end = (
                 (mouse_tile_x as usize).min(self.level.width - 1),
@@ -562,13 +1410,11 @@ impl<'a> GoblinMaker<'a> {
             );
 
             let tileset = if selection.1 == 0 {
-                &self.assets.terrain_tileset
+                self.assets.terrain_tileset()
             } else {
-                &self.assets.obstacles_tileset
+                self.assets.obstacles_tileset()
             };
-            let width = end.0.abs_diff(start.0) + 1;
-            let height = end.1.abs_diff(start.1) + 1;
-            let actual_start = (start.0.min(end.0), start.1.min(end.1));
+            let tiles = shape_tiles(self.shape_mode, start, end);
             let params = DrawTextureParams {
                 dest_size: Some(vec2(
                     16.0 * scale_factor * self.camera_zoom,
@@ -576,41 +1422,159 @@ impl<'a> GoblinMaker<'a> {
                 )),
                 ..Default::default()
             };
-            for x in 0..width {
-                let x = x + actual_start.0;
-                for y in 0..height {
-                    let y = y + actual_start.1;
-                    if released {
-                        let mut old = self.level.get_tile(x, y);
-                        old[selection.1 as usize] = selection.0 as u8 + 1;
-                        self.level_renderer.set_tile(&mut self.level, x, y, old);
-                    } else {
+            if released {
+                let changes = tiles
+                    .into_iter()
+                    .map(|(x, y)| {
+                        let mut tile = self.level.get_tile(x, y);
+                        tile[selection.1 as usize] = selection.0 as u8 + 1;
+                        (x, y, tile)
+                    })
+                    .collect();
+                self.history
+                    .set_tiles(&mut self.level, &mut self.level_renderer, changes);
+            } else {
+                for (x, y) in tiles {
+                    let mut params = params.clone();
+                    params.source = Some(Rect {
+                        x: (selection.0 % 3) as f32 * 16.0,
+                        y: (selection.0 / 3) as f32 * 16.0,
+                        w: 16.0,
+                        h: 16.0,
+                    });
+                    draw_texture_ex(
+                        &tileset.texture,
+                        (x * 16) as f32 * scale_factor * self.camera_zoom
+                            - self.camera_pos.x * scale_factor * self.camera_zoom,
+                        (y * 16) as f32 * scale_factor * self.camera_zoom
+                            - self.camera_pos.y * scale_factor * self.camera_zoom,
+                        WHITE.with_alpha(0.75),
+                        params,
+                    );
+                }
+            }
+            set_default_camera();
+        }
+        if let Tool::Select = &self.tool {
+            let to_screen = |x: f32, y: f32| {
+                (vec2(x, y) * 16.0 - self.camera_pos) * scale_factor * self.camera_zoom
+            };
+            if let Some((clipboard, offset)) = &self.floating {
+                let origin = vec2(
+                    (mouse_tile.0 - offset.0) as f32,
+                    (mouse_tile.1 - offset.1) as f32,
+                );
+                let params = DrawTextureParams {
+                    dest_size: Some(vec2(
+                        16.0 * scale_factor * self.camera_zoom,
+                        16.0 * scale_factor * self.camera_zoom,
+                    )),
+                    ..Default::default()
+                };
+                for (index, tile) in clipboard.tiles.iter().enumerate() {
+                    let pos = to_screen(
+                        origin.x + (index % clipboard.width) as f32,
+                        origin.y + (index / clipboard.width) as f32,
+                    );
+                    for (id, tileset) in tile.iter().zip([
+                        self.assets.terrain_tileset(),
+                        self.assets.obstacles_tileset(),
+                    ]) {
+                        if *id == 0 {
+                            continue;
+                        }
+                        let id = *id as usize - 1;
                         let mut params = params.clone();
                         params.source = Some(Rect {
-                            x: (selection.0 % 3) as f32 * 16.0,
-                            y: (selection.0 / 3) as f32 * 16.0,
+                            x: (id % 3) as f32 * 16.0,
+                            y: (id / 3) as f32 * 16.0,
                             w: 16.0,
                             h: 16.0,
                         });
                         draw_texture_ex(
                             &tileset.texture,
-                            (x * 16) as f32 * scale_factor * self.camera_zoom
-                                - self.camera_pos.x * scale_factor * self.camera_zoom,
-                            (y * 16) as f32 * scale_factor * self.camera_zoom
-                                - self.camera_pos.y * scale_factor * self.camera_zoom,
+                            pos.x,
+                            pos.y,
                             WHITE.with_alpha(0.75),
                             params,
                         );
                     }
                 }
+                for (pos, _, index) in clipboard.characters.iter() {
+                    let pos = to_screen(origin.x + pos.0 / 16.0, origin.y + pos.1 / 16.0);
+                    let mut params = params.clone();
+                    params.source = Some(Rect {
+                        x: (index % 3) as f32 * 16.0,
+                        y: (index / 3) as f32 * 16.0,
+                        w: 16.0,
+                        h: 16.0,
+                    });
+                    draw_texture_ex(
+                        &self.assets.character_tileset().texture,
+                        pos.x,
+                        pos.y,
+                        WHITE.with_alpha(0.75),
+                        params,
+                    );
+                }
             }
-            set_default_camera();
+            let outline = if let Some((clipboard, offset)) = &self.floating {
+                Some((
+                    vec2(
+                        (mouse_tile.0 - offset.0) as f32,
+                        (mouse_tile.1 - offset.1) as f32,
+                    ),
+                    vec2(clipboard.width as f32, clipboard.height() as f32),
+                ))
+            } else if let Dragging::WorldOwned(_, start) = self.dragging {
+                let end = (
+                    (mouse_tile_x.max(0.0) as usize).min(self.level.width - 1),
+                    (mouse_tile_y.max(0.0) as usize).min(self.level.height() - 1),
+                );
+                Some((
+                    vec2(start.0.min(end.0) as f32, start.1.min(end.1) as f32),
+                    vec2(
+                        (end.0.abs_diff(start.0) + 1) as f32,
+                        (end.1.abs_diff(start.1) + 1) as f32,
+                    ),
+                ))
+            } else {
+                self.selection.map(|(pos, size)| {
+                    (
+                        vec2(pos.0 as f32, pos.1 as f32),
+                        vec2(size.0 as f32, size.1 as f32),
+                    )
+                })
+            };
+            if let Some((pos, size)) = outline {
+                let pos = to_screen(pos.x, pos.y);
+                let size = size * 16.0 * scale_factor * self.camera_zoom;
+                draw_rectangle_lines(pos.x, pos.y, size.x, size.y, 2.0 * scale_factor, BLACK);
+                draw_rectangle_lines(
+                    pos.x + scale_factor,
+                    pos.y + scale_factor,
+                    size.x - 2.0 * scale_factor,
+                    size.y - 2.0 * scale_factor,
+                    scale_factor,
+                    WHITE,
+                );
+            }
+        }
+        // group everything done during one stroke or shape into a single undo step
+        if !is_mouse_button_down(MouseButton::Left) && self.history.commit(&self.level) {
+            self.update_flags();
+        }
+        let state = self.history.state();
+        if self.warnings.0 != Some(state) {
+            self.warnings = (Some(state), self.level.lint());
         }
 
         match &self.tool {
-            Tool::Pencil | Tool::Shape => {
+            Tool::Pencil | Tool::Shape | Tool::Fill => {
                 if let Some((index, tab)) = self.selected_tile
                     && let Some((tx, ty)) = cursor_tile
+                    && self.editable(tab)
+                    && self.moving_character.is_none()
                     && {
                         if tab == 2 {
                             true
@@ -620,9 +1584,9 @@ impl<'a> GoblinMaker<'a> {
                     }
                 {
                     let tileset = [
-                        &self.assets.terrain_tileset,
-                        &self.assets.obstacles_tileset,
-                        &self.assets.character_tileset,
+                        self.assets.terrain_tileset(),
+                        self.assets.obstacles_tileset(),
+                        self.assets.character_tileset(),
                     ][tab as usize];
                     let pos = vec2((tx * 16) as f32, (ty * 16) as f32);
                     let mut params = params.clone();
@@ -643,16 +1607,35 @@ impl<'a> GoblinMaker<'a> {
                     );
                 }
             }
+            Tool::Select => {}
             Tool::Eraser => {
-                if let Some((tx, ty)) = cursor_tile {
-                    let pos = vec2((tx * 16) as f32, (ty * 16) as f32);
+                let area = if self.eraser_mode == EraserMode::Brush {
+                    cursor_tile.map(|(tx, ty)| self.brush_area(tx, ty))
+                } else if let Dragging::WorldOwned(_, start) = self.dragging {
+                    let end = (
+                        (mouse_tile_x.max(0.0) as usize).min(self.level.width - 1),
+                        (mouse_tile_y.max(0.0) as usize).min(self.level.height() - 1),
+                    );
+                    Some((
+                        (start.0.min(end.0), start.1.min(end.1)),
+                        (start.0.max(end.0) + 1, start.1.max(end.1) + 1),
+                    ))
+                } else {
+                    cursor_tile.map(|(tx, ty)| ((tx, ty), (tx + 1, ty + 1)))
+                };
+                if let Some((start, end)) = area {
+                    let pos = vec2((start.0 * 16) as f32, (start.1 * 16) as f32);
+                    let size = vec2(
+                        ((end.0 - start.0) * 16) as f32,
+                        ((end.1 - start.1) * 16) as f32,
+                    );
                     draw_rectangle_lines(
                         (pos.x) * scale_factor * self.camera_zoom
                             - self.camera_pos.x * scale_factor * self.camera_zoom,
                         (pos.y) * scale_factor * self.camera_zoom
                             - self.camera_pos.y * scale_factor * self.camera_zoom,
-                        16.0 * scale_factor * self.camera_zoom,
-                        16.0 * scale_factor * self.camera_zoom,
+                        size.x * scale_factor * self.camera_zoom,
+                        size.y * scale_factor * self.camera_zoom,
                         1.0 * scale_factor * self.camera_zoom,
                         BLACK,
                     );
@@ -661,8 +1644,8 @@ impl<'a> GoblinMaker<'a> {
                             - self.camera_pos.x * scale_factor * self.camera_zoom,
                         (pos.y + 0.5) * scale_factor * self.camera_zoom
                             - self.camera_pos.y * scale_factor * self.camera_zoom,
-                        15.0 * scale_factor * self.camera_zoom,
-                        15.0 * scale_factor * self.camera_zoom,
+                        (size.x - 1.0) * scale_factor * self.camera_zoom,
+                        (size.y - 1.0) * scale_factor * self.camera_zoom,
                         1.0 * scale_factor * self.camera_zoom,
                         WHITE,
                     );
@@ -687,79 +1670,660 @@ impl<'a> GoblinMaker<'a> {
         for btn in tile_btns {
             btn.draw();
         }
+        for (btn, delete_btn) in stamp_btns {
+            btn.draw();
+            if let Some(delete_btn) = delete_btn {
+                delete_btn.draw();
+            }
+        }
         for btn in tool_btns {
             btn.draw();
         }
         play_btn.draw();
 
-        if self.menu_open {
-            let size = vec2(150.0, 150.0);
-            let pos = ((vec2(actual_screen_width, actual_screen_height) - size * scale_factor)
-                / 2.0)
-                .floor();
-            let rect = UIRect::new(
-                pos,
-                size * scale_factor,
-                MAKER_BG_COLOR,
-                (scale_factor, BLACK),
-            );
-            rect.draw();
-            let font_size = (20.0 * scale_factor) as u16;
-            draw_text_ex(
-                "Paused",
-                pos.x + 5.0 * scale_factor,
-                pos.y + font_size as f32,
-                TextParams {
-                    font_size,
-                    font: Some(&self.assets.font),
-                    ..Default::default()
-                },
-            );
-            let font_size = (12.0 * scale_factor) as u16;
-            let btn_size = vec2(135.0, 20.0);
-            let resume = UITextButton::new(
-                pos + vec2((size.x - btn_size.x) / 2.0, size.y - 3.0 * btn_size.y - 9.0)
-                    * scale_factor,
-                btn_size * scale_factor,
-                "Resume".to_string(),
-                SKY_COLOR,
-                MAKER_BG_COLOR,
-                (scale_factor, BLACK),
-                (font_size, &self.assets.font, 5.0 * scale_factor),
-            );
-            if resume.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
-                self.menu_open = false;
+        match &mut self.menu {
+            MakerMenu::Closed => {}
+            MakerMenu::Paused => {
+                let buttons = [
+                    "Resume",
+                    "Resize Level",
+                    "Exit without Saving",
+                    "Save as New Level",
+                    "Save and Exit",
+                ];
+                let btn_size = vec2(135.0, 20.0);
+                let size = vec2(150.0, 150.0);
+                // make room for the issues list on the right
+                let issues_offset = if self.warnings.1.is_empty() {
+                    0.0
+                } else {
+                    92.0
+                };
+                let pos = ((vec2(actual_screen_width, actual_screen_height) - size * scale_factor)
+                    / 2.0
+                    - vec2(issues_offset, 0.0) * scale_factor)
+                    .floor();
+                let rect = UIRect::new(
+                    pos,
+                    size * scale_factor,
+                    MAKER_BG_COLOR,
+                    (scale_factor, BLACK),
+                );
+                rect.draw();
+                let font_size = (20.0 * scale_factor) as u16;
+                draw_text_ex(
+                    "Paused",
+                    pos.x + 5.0 * scale_factor,
+                    pos.y + font_size as f32,
+                    TextParams {
+                        font_size,
+                        font: Some(self.assets.font()),
+                        ..Default::default()
+                    },
+                );
+                let font_size = (12.0 * scale_factor) as u16;
+                for (i, text) in buttons.iter().enumerate() {
+                    let from_bottom = (buttons.len() - i) as f32;
+                    let btn = UITextButton::new(
+                        pos + vec2(
+                            (size.x - btn_size.x) / 2.0,
+                            size.y - from_bottom * btn_size.y - 3.0 - 2.0 * from_bottom,
+                        ) * scale_factor,
+                        btn_size * scale_factor,
+                        text.to_string(),
+                        SKY_COLOR,
+                        MAKER_BG_COLOR,
+                        (scale_factor, BLACK),
+                        (font_size, self.assets.font(), 5.0 * scale_factor),
+                    );
+                    btn.draw();
+                    if btn.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
+                        match i {
+                            0 => self.menu = MakerMenu::Closed,
+                            1 => {
+                                self.menu = MakerMenu::Resize(ResizeData {
+                                    width: TextInputData::from_text(self.level.width.to_string()),
+                                    height: TextInputData::from_text(
+                                        self.level.height().to_string(),
+                                    ),
+                                    anchor: (1, 1),
+                                })
+                            }
+                            2 if self.modified => self.menu = MakerMenu::ConfirmExit,
+                            2 => return MakerUpdateResult::ExitNoSave,
+                            3 => {
+                                self.menu = MakerMenu::SaveAs(TextInputData::from_text(
+                                    self.name.clone().unwrap_or_default(),
+                                ))
+                            }
+                            4 => return MakerUpdateResult::SaveAndExit,
+                            _ => panic!(),
+                        }
+                    }
+                }
+                // list problems in the level next to the menu
+                if !self.warnings.1.is_empty() {
+                    const MAX_LINES: usize = 9;
+                    let pos = pos + vec2(size.x + 4.0, 0.0) * scale_factor;
+                    let rect = UIRect::new(
+                        pos,
+                        vec2(180.0, size.y) * scale_factor,
+                        MAKER_BG_COLOR,
+                        (scale_factor, BLACK),
+                    );
+                    rect.draw();
+                    let font_size = (20.0 * scale_factor) as u16;
+                    draw_text_ex(
+                        "Issues",
+                        pos.x + 5.0 * scale_factor,
+                        pos.y + font_size as f32,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                    let mut lines: Vec<String> = self
+                        .warnings
+                        .1
+                        .iter()
+                        .take(MAX_LINES)
+                        .map(|f| f.to_string())
+                        .collect();
+                    if self.warnings.1.len() > MAX_LINES {
+                        lines.push(format!("...and {} more", self.warnings.1.len() - MAX_LINES));
+                    }
+                    let font_size = (10.0 * scale_factor) as u16;
+                    for (i, line) in lines.iter().enumerate() {
+                        draw_text_ex(
+                            line,
+                            pos.x + 5.0 * scale_factor,
+                            pos.y + (28.0 + i as f32 * 12.0) * scale_factor + font_size as f32,
+                            TextParams {
+                                font_size,
+                                font: Some(self.assets.font()),
+                                ..Default::default()
+                            },
+                        );
+                    }
+                }
             }
-            resume.draw();
-            let save_no_exit = UITextButton::new(
-                pos + vec2((size.x - btn_size.x) / 2.0, size.y - 2.0 * btn_size.y - 7.0)
-                    * scale_factor,
-                btn_size * scale_factor,
-                "Exit without Saving".to_string(),
-                SKY_COLOR,
-                MAKER_BG_COLOR,
-                (scale_factor, BLACK),
-                (font_size, &self.assets.font, 5.0 * scale_factor),
-            );
-            save_no_exit.draw();
-            if save_no_exit.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
-                return MakerUpdateResult::ExitNoSave;
-            }
-            let save_and_exit = UITextButton::new(
-                pos + vec2((size.x - btn_size.x) / 2.0, size.y - btn_size.y - 5.0) * scale_factor,
-                btn_size * scale_factor,
-                "Save and Exit".to_string(),
-                SKY_COLOR,
-                MAKER_BG_COLOR,
-                (scale_factor, BLACK),
-                (font_size, &self.assets.font, 5.0 * scale_factor),
-            );
-            save_and_exit.draw();
-            if save_and_exit.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
-                return MakerUpdateResult::SaveAndExit;
+            MakerMenu::Resize(data) => {
+                let size = vec2(200.0, 120.0);
+                let pos = ((vec2(actual_screen_width, actual_screen_height) - size * scale_factor)
+                    / 2.0)
+                    .floor();
+                let rect = UIRect::new(
+                    pos,
+                    size * scale_factor,
+                    MAKER_BG_COLOR,
+                    (scale_factor, BLACK),
+                );
+                rect.draw();
+                let font_size = (20.0 * scale_factor) as u16;
+                draw_text_ex(
+                    "Resize Level",
+                    pos.x + 5.0 * scale_factor,
+                    pos.y + font_size as f32,
+                    TextParams {
+                        font_size,
+                        font: Some(self.assets.font()),
+                        ..Default::default()
+                    },
+                );
+                let font_size = (12.0 * scale_factor) as u16;
+                let input_size = vec2(50.0, 20.0);
+                for (i, (label, input)) in
+                    [("Width", &mut data.width), ("Height", &mut data.height)]
+                        .into_iter()
+                        .enumerate()
+                {
+                    let y = 30.0 + i as f32 * (input_size.y + 5.0);
+                    draw_text_ex(
+                        label,
+                        pos.x + 5.0 * scale_factor,
+                        pos.y + y * scale_factor + font_size as f32,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                    let mut input = UITextInput::new(
+                        pos + vec2(55.0, y) * scale_factor,
+                        input_size * scale_factor,
+                        SKY_COLOR,
+                        MAKER_BG_COLOR,
+                        (scale_factor, BLACK),
+                        (font_size, self.assets.font(), 3.0 * scale_factor),
+                        input,
+                        "",
+                        3,
+                    );
+                    input.draw();
+                }
+
+                // grid of buttons for choosing which side or corner stays in place
+                let anchor_size = 14.0;
+                for x in 0..3 {
+                    for y in 0..3 {
+                        let selected = data.anchor == (x, y);
+                        let btn = UIRect::new(
+                            pos + (vec2(size.x - 5.0 - 3.0 * (anchor_size + 2.0), 30.0)
+                                + vec2(x as f32, y as f32) * (anchor_size + 2.0))
+                                * scale_factor,
+                            vec2(anchor_size, anchor_size) * scale_factor,
+                            if selected { WHITE } else { SKY_COLOR },
+                            (scale_factor, BLACK),
+                        );
+                        btn.draw();
+                        if btn.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
+                            data.anchor = (x, y);
+                        }
+                    }
+                }
+
+                let btn_size = vec2(92.0, 20.0);
+                let mut apply = None;
+                let mut back = false;
+                for (i, text) in ["Apply", "Back"].iter().enumerate() {
+                    let btn = UITextButton::new(
+                        pos + vec2(
+                            5.0 + i as f32 * (btn_size.x + 6.0),
+                            size.y - btn_size.y - 5.0,
+                        ) * scale_factor,
+                        btn_size * scale_factor,
+                        text.to_string(),
+                        SKY_COLOR,
+                        MAKER_BG_COLOR,
+                        (scale_factor, BLACK),
+                        (font_size, self.assets.font(), 5.0 * scale_factor),
+                    );
+                    btn.draw();
+                    if btn.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
+                        if i == 0 {
+                            let parse = |f: &TextInputData| {
+                                f.text
+                                    .trim()
+                                    .parse::<usize>()
+                                    .ok()
+                                    .map(|f| f.clamp(MIN_LEVEL_SIZE, MAX_LEVEL_SIZE))
+                            };
+                            if let (Some(width), Some(height)) =
+                                (parse(&data.width), parse(&data.height))
+                            {
+                                apply = Some((width, height, data.anchor));
+                            }
+                        } else {
+                            back = true;
+                        }
+                    }
+                }
+                if let Some((width, height, anchor)) = apply {
+                    self.resize_level(width, height, anchor);
+                    back = true;
+                }
+                if back {
+                    self.menu = MakerMenu::Paused;
+                }
+            }
+            MakerMenu::SaveStamp(data) => {
+                let size = vec2(150.0, 80.0);
+                let pos = ((vec2(actual_screen_width, actual_screen_height) - size * scale_factor)
+                    / 2.0)
+                    .floor();
+                let rect = UIRect::new(
+                    pos,
+                    size * scale_factor,
+                    MAKER_BG_COLOR,
+                    (scale_factor, BLACK),
+                );
+                rect.draw();
+                let font_size = (20.0 * scale_factor) as u16;
+                draw_text_ex(
+                    "Save Stamp",
+                    pos.x + 5.0 * scale_factor,
+                    pos.y + font_size as f32,
+                    TextParams {
+                        font_size,
+                        font: Some(self.assets.font()),
+                        ..Default::default()
+                    },
+                );
+                let font_size = (12.0 * scale_factor) as u16;
+                let mut input = UITextInput::new(
+                    pos + vec2(5.0, 28.0) * scale_factor,
+                    vec2(size.x - 10.0, 20.0) * scale_factor,
+                    SKY_COLOR,
+                    MAKER_BG_COLOR,
+                    (scale_factor, BLACK),
+                    (font_size, self.assets.font(), 3.0 * scale_factor),
+                    data,
+                    "name",
+                    MAX_STAMP_NAME_LENGTH,
+                );
+                input.draw();
+
+                let btn_size = vec2(67.0, 20.0);
+                let mut save = None;
+                let mut back = false;
+                for (i, text) in ["Save", "Back"].iter().enumerate() {
+                    let btn = UITextButton::new(
+                        pos + vec2(
+                            5.0 + i as f32 * (btn_size.x + 6.0),
+                            size.y - btn_size.y - 5.0,
+                        ) * scale_factor,
+                        btn_size * scale_factor,
+                        text.to_string(),
+                        SKY_COLOR,
+                        MAKER_BG_COLOR,
+                        (scale_factor, BLACK),
+                        (font_size, self.assets.font(), 5.0 * scale_factor),
+                    );
+                    btn.draw();
+                    if btn.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
+                        if i == 0 {
+                            let name = data.text.trim();
+                            if !name.is_empty() {
+                                save = Some(name.to_string());
+                            }
+                        } else {
+                            back = true;
+                        }
+                    }
+                }
+                if let Some(name) = save
+                    && let Some(stamp) = self.copy_selection()
+                {
+                    // saving with an existing name replaces that stamp
+                    if let Some(old) = self.stamps.iter_mut().find(|f| f.0 == name) {
+                        old.1 = stamp;
+                    } else {
+                        self.stamps.push((name, stamp));
+                    }
+                    result = MakerUpdateResult::StampsChanged;
+                    back = true;
+                }
+                if back {
+                    self.menu = MakerMenu::Closed;
+                }
+            }
+            MakerMenu::SaveAs(data) => {
+                let size = vec2(200.0, 90.0);
+                let pos = ((vec2(actual_screen_width, actual_screen_height) - size * scale_factor)
+                    / 2.0)
+                    .floor();
+                let rect = UIRect::new(
+                    pos,
+                    size * scale_factor,
+                    MAKER_BG_COLOR,
+                    (scale_factor, BLACK),
+                );
+                rect.draw();
+                let font_size = (20.0 * scale_factor) as u16;
+                draw_text_ex(
+                    "Save as New Level",
+                    pos.x + 5.0 * scale_factor,
+                    pos.y + font_size as f32,
+                    TextParams {
+                        font_size,
+                        font: Some(self.assets.font()),
+                        ..Default::default()
+                    },
+                );
+                let font_size = (12.0 * scale_factor) as u16;
+                let mut input = UITextInput::new(
+                    pos + vec2(5.0, 28.0) * scale_factor,
+                    vec2(size.x - 10.0, 20.0) * scale_factor,
+                    SKY_COLOR,
+                    MAKER_BG_COLOR,
+                    (scale_factor, BLACK),
+                    (font_size, self.assets.font(), 3.0 * scale_factor),
+                    data,
+                    "Enter level name",
+                    MAX_LEVEL_NAME_LENGTH,
+                );
+                input.draw();
+                // same rules as renaming, the name can't belong to another level
+                let name =
+                    Data::check_level_name(&data.text, self.level_names.iter().map(String::as_str));
+                if let Err(e) = name
+                    && !data.text.is_empty()
+                {
+                    let font_size = (10.0 * scale_factor) as u16;
+                    draw_text_ex(
+                        e,
+                        pos.x + 5.0 * scale_factor,
+                        pos.y + 50.0 * scale_factor + font_size as f32,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
+                            color: RED,
+                            ..Default::default()
+                        },
+                    );
+                }
+
+                let btn_size = vec2(92.0, 20.0);
+                for (i, text) in ["Save and Exit", "Back"].iter().enumerate() {
+                    let btn = UITextButton::new(
+                        pos + vec2(
+                            5.0 + i as f32 * (btn_size.x + 6.0),
+                            size.y - btn_size.y - 5.0,
+                        ) * scale_factor,
+                        btn_size * scale_factor,
+                        text.to_string(),
+                        SKY_COLOR,
+                        MAKER_BG_COLOR,
+                        (scale_factor, BLACK),
+                        (font_size, self.assets.font(), 5.0 * scale_factor),
+                    );
+                    btn.draw();
+                    if btn.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
+                        if i == 1 {
+                            self.menu = MakerMenu::Paused;
+                        } else if let Ok(name) = &name {
+                            self.name = Some(name.clone());
+                            return MakerUpdateResult::SaveAndExit;
+                        }
+                    }
+                }
+            }
+            MakerMenu::ConfirmExit => {
+                let size = vec2(200.0, 80.0);
+                let pos = ((vec2(actual_screen_width, actual_screen_height) - size * scale_factor)
+                    / 2.0)
+                    .floor();
+                let rect = UIRect::new(
+                    pos,
+                    size * scale_factor,
+                    MAKER_BG_COLOR,
+                    (scale_factor, BLACK),
+                );
+                rect.draw();
+                let font_size = (20.0 * scale_factor) as u16;
+                draw_text_ex(
+                    "Unsaved Changes",
+                    pos.x + 5.0 * scale_factor,
+                    pos.y + font_size as f32,
+                    TextParams {
+                        font_size,
+                        font: Some(self.assets.font()),
+                        ..Default::default()
+                    },
+                );
+                let font_size = (12.0 * scale_factor) as u16;
+                draw_text_ex(
+                    "Save changes before exiting?",
+                    pos.x + 5.0 * scale_factor,
+                    pos.y + 28.0 * scale_factor + font_size as f32,
+                    TextParams {
+                        font_size,
+                        font: Some(self.assets.font()),
+                        ..Default::default()
+                    },
+                );
+
+                let btn_size = vec2(60.0, 20.0);
+                for (i, text) in ["Save", "Discard", "Cancel"].iter().enumerate() {
+                    let btn = UITextButton::new(
+                        pos + vec2(
+                            5.0 + i as f32 * (btn_size.x + 5.0),
+                            size.y - btn_size.y - 5.0,
+                        ) * scale_factor,
+                        btn_size * scale_factor,
+                        text.to_string(),
+                        SKY_COLOR,
+                        MAKER_BG_COLOR,
+                        (scale_factor, BLACK),
+                        (font_size, self.assets.font(), 5.0 * scale_factor),
+                    );
+                    btn.draw();
+                    if btn.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
+                        match i {
+                            0 => return MakerUpdateResult::SaveAndExit,
+                            1 => return MakerUpdateResult::ExitNoSave,
+                            _ => self.menu = MakerMenu::Closed,
+                        }
+                    }
+                }
+            }
+            MakerMenu::Inspect(index) => 'inspect: {
+                let index = *index;
+                let Some((
+                    _,
+                    Character::WanderEnemy {
+                        animation,
+                        moving_left,
+                        speed,
+                        patrol_range,
+                        turn_at_ledges,
+                    },
+                    _,
+                )) = self.level.characters.get(index).cloned()
+                else {
+                    // the character is gone, such as after undoing
+                    self.menu = MakerMenu::Closed;
+                    break 'inspect;
+                };
+                let size = vec2(160.0, 130.0);
+                let pos = ((vec2(actual_screen_width, actual_screen_height) - size * scale_factor)
+                    / 2.0)
+                    .floor();
+                let rect = UIRect::new(
+                    pos,
+                    size * scale_factor,
+                    MAKER_BG_COLOR,
+                    (scale_factor, BLACK),
+                );
+                rect.draw();
+                let font_size = (20.0 * scale_factor) as u16;
+                draw_text_ex(
+                    "Enemy",
+                    pos.x + 5.0 * scale_factor,
+                    pos.y + font_size as f32,
+                    TextParams {
+                        font_size,
+                        font: Some(self.assets.font()),
+                        ..Default::default()
+                    },
+                );
+                let font_size = (12.0 * scale_factor) as u16;
+                let clicked = |btn: &UITextButton| {
+                    btn.draw();
+                    btn.is_hovered() && is_mouse_button_pressed(MouseButton::Left)
+                };
+                let text_button = |pos: Vec2, width: f32, text: &str| {
+                    UITextButton::new(
+                        pos * scale_factor,
+                        vec2(width, 18.0) * scale_factor,
+                        text.to_string(),
+                        SKY_COLOR,
+                        MAKER_BG_COLOR,
+                        (scale_factor, BLACK),
+                        (font_size, self.assets.font(), 4.0 * scale_factor),
+                    )
+                };
+                let row_pos = |row: usize| pos / scale_factor + vec2(5.0, 28.0 + row as f32 * 20.0);
+
+                let mut new = (moving_left, speed, patrol_range, turn_at_ledges);
+                let values = [
+                    ("Direction", None),
+                    ("Speed", Some(format!("{speed}"))),
+                    (
+                        "Patrol",
+                        Some(if patrol_range == 0 {
+                            "Any".to_string()
+                        } else {
+                            format!("{patrol_range}")
+                        }),
+                    ),
+                    ("Ledges", None),
+                ];
+                for (row, (label, value)) in values.into_iter().enumerate() {
+                    let row_pos = row_pos(row);
+                    draw_text_ex(
+                        label,
+                        row_pos.x * scale_factor,
+                        (row_pos.y + 4.0) * scale_factor + font_size as f32,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                    let controls = row_pos + vec2(70.0, 0.0);
+                    let Some(value) = value else {
+                        // toggles
+                        let text = match row {
+                            0 if moving_left => "Left",
+                            0 => "Right",
+                            _ if turn_at_ledges => "Turn",
+                            _ => "Walk off",
+                        };
+                        if clicked(&text_button(controls, 80.0, text)) {
+                            if row == 0 {
+                                new.0 = !new.0;
+                            } else {
+                                new.3 = !new.3;
+                            }
+                        }
+                        continue;
+                    };
+                    // number with buttons to step it down and up
+                    let minus = clicked(&text_button(controls, 18.0, "-"));
+                    let plus = clicked(&text_button(controls + vec2(62.0, 0.0), 18.0, "+"));
+                    draw_text_ex(
+                        &value,
+                        (controls.x + 22.0) * scale_factor,
+                        (controls.y + 4.0) * scale_factor + font_size as f32,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                    let step = plus as i32 - minus as i32;
+                    if row == 1 {
+                        new.1 = (speed + step as f32 * 8.0).clamp(MIN_ENEMY_SPEED, MAX_ENEMY_SPEED);
+                    } else {
+                        new.2 =
+                            (patrol_range as i32 + step).clamp(0, MAX_PATROL_RANGE as i32) as u8;
+                    }
+                }
+                if new != (moving_left, speed, patrol_range, turn_at_ledges) {
+                    self.history.modify_characters(&self.level);
+                    self.level.characters[index].1 = Character::WanderEnemy {
+                        animation,
+                        moving_left: new.0,
+                        speed: new.1,
+                        patrol_range: new.2,
+                        turn_at_ledges: new.3,
+                    };
+                }
+                if clicked(&text_button(
+                    pos / scale_factor + vec2(5.0, size.y - 23.0),
+                    size.x - 10.0,
+                    "Done",
+                )) {
+                    self.menu = MakerMenu::Closed;
+                }
+            }
+        }
+
+        self.autosave_timer += delta_time;
+        if self.autosave_timer >= AUTOSAVE_INTERVAL && matches!(result, MakerUpdateResult::None) {
+            self.autosave_timer = 0.0;
+            let state = self.history.state();
+            if state != self.autosave_state {
+                self.autosave_state = state;
+                result = MakerUpdateResult::Autosave;
             }
         }
 
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::test_levels::empty_level;
+
+    #[test]
+    fn only_same_shape_transforms_fit_the_selection() {
+        let mut region = empty_level(3, 2);
+        region.flip(true);
+        assert!(fits_selection(&region, (3, 2)));
+        region.flip(false);
+        assert!(fits_selection(&region, (3, 2)));
+        // rotating a non-square area would reach past the selection
+        region.rotate();
+        assert!(!fits_selection(&region, (3, 2)));
+        assert!(fits_selection(&region, (2, 3)));
+
+        let mut square = empty_level(3, 3);
+        square.rotate();
+        assert!(fits_selection(&square, (3, 3)));
+    }
+}
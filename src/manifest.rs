@@ -0,0 +1,165 @@
+//! Mod for the manifest of an asset pack, which lists every asset of the pack along with what it's used for.
+//!
+//! The manifest is `manifest.ron` at the root of a pack, and every path in it is relative to that root.
+//! This file is also used by the build script to embed the default pack, so it only depends on nanoserde.
+
+use std::collections::HashSet;
+
+use nanoserde::DeRon;
+
+pub use optional_entries::{LayerEntry, TilesetEntry};
+
+/// A single file, used as-is
+#[derive(DeRon)]
+pub struct FileEntry {
+    pub id: String,
+    pub path: String,
+}
+
+// the DeRon derive generates code that trips this lint for `Option` fields
+#[allow(clippy::question_mark)]
+mod optional_entries {
+    use nanoserde::DeRon;
+
+    /// An aseprite file, of which either a single layer or all layers together are used
+    #[derive(DeRon)]
+    pub struct LayerEntry {
+        pub id: String,
+        pub path: String,
+        #[nserde(default)]
+        pub layer: Option<u32>,
+    }
+
+    #[derive(DeRon)]
+    pub struct TilesetEntry {
+        pub id: String,
+        pub path: String,
+        #[nserde(default)]
+        pub layer: Option<u32>,
+        /// Autotile sheets of tiles in the tileset (see [crate::autotile])
+        #[nserde(default)]
+        pub autotiles: Vec<String>,
+    }
+}
+
+/// Ids of the assets that fill the roles the game itself needs
+#[derive(Clone, DeRon)]
+pub struct Roles {
+    pub font: String,
+    pub terrain: String,
+    pub obstacles: String,
+    pub characters: String,
+    pub enemies: String,
+    pub player_torso: String,
+    pub player_legs: String,
+    pub player_die: String,
+}
+
+#[derive(DeRon)]
+pub struct Manifest {
+    pub fonts: Vec<FileEntry>,
+    pub textures: Vec<LayerEntry>,
+    /// Single animations, where every frame is a step of the animation
+    pub animations: Vec<FileEntry>,
+    /// Files with several animations, one per tag
+    pub animation_groups: Vec<FileEntry>,
+    pub tilesets: Vec<TilesetEntry>,
+    pub roles: Roles,
+}
+impl Manifest {
+    /// Path of every file used by the pack
+    pub fn files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = self
+            .fonts
+            .iter()
+            .chain(self.animations.iter())
+            .chain(self.animation_groups.iter())
+            .map(|f| f.path.as_str())
+            .chain(self.textures.iter().map(|f| f.path.as_str()))
+            .collect();
+        for tileset in self.tilesets.iter() {
+            files.push(&tileset.path);
+            files.extend(tileset.autotiles.iter().map(|f| f.as_str()));
+        }
+        files.sort();
+        files.dedup();
+        files
+    }
+    /// Ids of every asset, grouped by kind
+    pub fn ids(&self) -> [(&'static str, Vec<&str>); 5] {
+        fn ids<'a>(ids: impl Iterator<Item = &'a String>) -> Vec<&'a str> {
+            ids.map(|f| f.as_str()).collect()
+        }
+        [
+            ("font", ids(self.fonts.iter().map(|f| &f.id))),
+            ("texture", ids(self.textures.iter().map(|f| &f.id))),
+            ("animation", ids(self.animations.iter().map(|f| &f.id))),
+            (
+                "animation group",
+                ids(self.animation_groups.iter().map(|f| &f.id)),
+            ),
+            ("tileset", ids(self.tilesets.iter().map(|f| &f.id))),
+        ]
+    }
+    /// Checks that every file of the manifest exists, that every role refers to an asset of the right kind,
+    /// and that every asset of `required` has a counterpart of the same kind and id.
+    ///
+    /// Returns a description of each problem found.
+    pub fn check(
+        &self,
+        required: &Manifest,
+        has_file: impl Fn(&str) -> bool,
+    ) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for path in self.files() {
+            if !has_file(path) {
+                errors.push(format!("{path} is missing"));
+            }
+        }
+
+        let ids = self.ids();
+        for (kind, ids) in ids.iter() {
+            let mut seen = HashSet::new();
+            for id in ids {
+                if !seen.insert(id) {
+                    errors.push(format!("{kind} id {id} is used twice"));
+                }
+            }
+        }
+        let exists = |kind: &str, id: &str| ids.iter().any(|f| f.0 == kind && f.1.contains(&id));
+
+        let roles = &self.roles;
+        for (role, kind, id) in [
+            ("font", "font", &roles.font),
+            ("terrain", "tileset", &roles.terrain),
+            ("obstacles", "tileset", &roles.obstacles),
+            ("characters", "tileset", &roles.characters),
+            ("enemies", "animation group", &roles.enemies),
+            ("player_torso", "animation group", &roles.player_torso),
+            ("player_legs", "animation group", &roles.player_legs),
+            ("player_die", "texture", &roles.player_die),
+        ] {
+            if !exists(kind, id) {
+                errors.push(format!("{role} role refers to unknown {kind} {id}"));
+            }
+        }
+
+        for (kind, ids) in required.ids() {
+            for id in ids {
+                if !exists(kind, id) {
+                    errors.push(format!("{kind} {id} is missing"));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+}
+
+/// Path of the manifest, relative to the root of the pack
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+pub const MANIFEST_PATH: &str = "manifest.ron";
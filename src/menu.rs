@@ -1,34 +1,55 @@
-use crate::{assets::Assets, data::*, level::Level, ui::*, utils::*};
+use crate::{
+    assets::{Assets, ids},
+    data::*,
+    level::{Level, LevelLimits, LevelRenderer, describe_errors},
+    thumbnail::{Thumbnail, ThumbnailRenderer},
+    ui::*,
+    utils::*,
+};
 use macroquad::{miniquad::window::screen_size, prelude::*};
 
 pub enum MenuUpdateResult {
     None,
     Create(Option<usize>),
     PlayOnline(Level, String, String),
+    /// Open the autosaved level from the recovery slot in the editor
+    Recover,
 }
 enum LevelMenuType {
     Closed,
     BrowseOnline,
     LocalLevels,
 }
+/// Which level list entry a thumbnail shows: whether it's online, the level name (or online id),
+/// and the [Data::local_generation] of local levels, as they can change under the same name
+type ThumbnailKey = (bool, String, u32);
+
 enum PopupMenu {
     None,
     Delete(usize),
     Rename(usize, TextInputData),
     Upload(usize, TextInputData, TextInputData),
+    Import(TextInputData),
+    Exported(String),
     VerificationRequired,
     Uploading,
     Downloading,
     Error(String),
+    /// Offers restoring the autosaved level, if the game didn't close cleanly
+    Recover,
+    /// Lists the saved versions of a local level, with which of them is selected (newest first)
+    History(usize, usize),
 }
 impl PopupMenu {
     fn yes_button(&self) -> bool {
         !matches!(
             self,
             PopupMenu::Error(_)
+                | PopupMenu::Exported(_)
                 | PopupMenu::Uploading
                 | PopupMenu::Downloading
                 | PopupMenu::VerificationRequired
+                | PopupMenu::History(_, _)
         )
     }
 }
@@ -38,19 +59,51 @@ pub struct MainMenu<'a> {
     scroll: f32,
     time: f32,
     popup: PopupMenu,
+    limits: LevelLimits,
+    /// Rendered snapshot shown in the history popup, by level and snapshot index
+    preview: Option<((usize, usize), LevelRenderer<'a>)>,
+    thumbnails: ThumbnailRenderer,
+    /// Generation of the assets `limits` and `thumbnails` were built from, see [Assets::generation]
+    assets_generation: u32,
+    /// Thumbnail of the level list entry that was last hovered
+    thumbnail: Option<(ThumbnailKey, Texture2D)>,
+    /// Thumbnail of the hovered entry while it is rendered, a few rows each frame
+    pending_thumbnail: Option<(ThumbnailKey, Thumbnail)>,
 }
 impl<'a> MainMenu<'a> {
-    pub fn new(assets: &'a Assets) -> Self {
+    pub fn new(assets: &'a Assets, data: &Data) -> Self {
         Self {
             assets,
             level_menu: LevelMenuType::Closed,
             scroll: 0.0,
             time: 0.0,
-            popup: PopupMenu::None,
+            popup: if data.recovery.is_some() {
+                PopupMenu::Recover
+            } else {
+                PopupMenu::None
+            },
+            limits: LevelLimits::new(assets),
+            preview: None,
+            thumbnails: ThumbnailRenderer::from_pack(&assets.pack()),
+            assets_generation: assets.generation(),
+            thumbnail: None,
+            pending_thumbnail: None,
         }
     }
     pub fn update(&mut self, data: &mut Data) -> MenuUpdateResult {
         let delta_time = get_frame_time();
+        if self.assets_generation != self.assets.generation() {
+            self.limits = LevelLimits::new(self.assets);
+            self.thumbnails = ThumbnailRenderer::from_pack(&self.assets.pack());
+            self.assets_generation = self.assets.generation();
+            self.thumbnail = None;
+            self.pending_thumbnail = None;
+        }
+        if let PopupMenu::None = self.popup
+            && let Some(e) = data.store_error.take()
+        {
+            self.popup = PopupMenu::Error(format!("Couldn't save: {e}"));
+        }
         let (actual_screen_width, actual_screen_height) = screen_size();
         let scale_factor =
             (actual_screen_width / SCREEN_WIDTH).min(actual_screen_height / SCREEN_HEIGHT);
@@ -68,15 +121,15 @@ impl<'a> MainMenu<'a> {
         let buttons_start = vec2(22.0, 113.0);
         let play_btn = UIImageButton::new(
             buttons_start * scale_factor,
-            &self.assets.menu_play_btn.frames[0].0,
-            &self.assets.menu_play_btn.frames[1].0,
+            &self.assets.animation(ids::MENU_PLAY_BTN).frames[0].0,
+            &self.assets.animation(ids::MENU_PLAY_BTN).frames[1].0,
             scale_factor,
             false,
         );
         let create_btn = UIImageButton::new(
             (buttons_start + vec2(0.0, 36.0)) * scale_factor,
-            &self.assets.menu_create_btn.frames[0].0,
-            &self.assets.menu_create_btn.frames[1].0,
+            &self.assets.animation(ids::MENU_CREATE_BTN).frames[0].0,
+            &self.assets.animation(ids::MENU_CREATE_BTN).frames[1].0,
             scale_factor,
             false,
         );
@@ -110,7 +163,9 @@ impl<'a> MainMenu<'a> {
                 if data.list_request.is_some() {
                     // if fetch request is active, show spinner
                     draw_texture_ex(
-                        self.assets.spinner.get_at_time((self.time * 1000.0) as u32),
+                        self.assets
+                            .animation(ids::SPINNER)
+                            .get_at_time((self.time * 1000.0) as u32),
                         menu_pos.x + (menu_size.x / 2.0 - 20.0) * scale_factor,
                         menu_pos.y + 70.0 * scale_factor,
                         WHITE,
@@ -127,7 +182,7 @@ impl<'a> MainMenu<'a> {
                         menu_pos.y + 70.0 * scale_factor,
                     );
                     draw_texture_ex(
-                        &self.assets.warning,
+                        self.assets.texture(ids::WARNING),
                         pos.x,
                         pos.y,
                         WHITE,
@@ -143,7 +198,7 @@ impl<'a> MainMenu<'a> {
                         pos.y + font_size as f32,
                         TextParams {
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
@@ -179,6 +234,7 @@ impl<'a> MainMenu<'a> {
             let font_size = (16.0 * scale_factor) as u16;
             buttons_pos.y += self.scroll * scale_factor;
 
+            let mut hovered = None;
             for (i, name) in names.iter().enumerate() {
                 let y = (offset.y * (i + 2) as f32) * scale_factor + buttons_pos.y;
                 let outside_scroll_box = y + size.y * scale_factor
@@ -188,14 +244,17 @@ impl<'a> MainMenu<'a> {
                 let mut item_buttons = Vec::new();
                 if matches!(self.level_menu, LevelMenuType::LocalLevels) {
                     let item_offset = vec2(17.0, 0.0);
-                    for (j, anim) in [
-                        &self.assets.delete_btn,
-                        &self.assets.rename_btn,
-                        &self.assets.upload_btn,
-                    ]
-                    .iter()
-                    .enumerate()
-                    {
+                    let actions = [
+                        self.assets.animation(ids::DELETE_BTN),
+                        self.assets.animation(ids::RENAME_BTN),
+                        self.assets.animation(ids::UPLOAD_BTN),
+                        self.assets.animation(ids::HISTORY_BTN),
+                        self.assets.animation(ids::DUPLICATE_BTN),
+                        // exporting writes to disk, so only available on native builds
+                        #[cfg(not(target_arch = "wasm32"))]
+                        self.assets.animation(ids::EXPORT_BTN),
+                    ];
+                    for (j, anim) in actions.iter().enumerate() {
                         let btn = UIImageButton::new(
                             (offset * (i + 2) as f32 + size - 16.0 - 5.0 - item_offset * j as f32)
                                 * scale_factor
@@ -238,6 +297,27 @@ impl<'a> MainMenu<'a> {
                                         self.popup = PopupMenu::VerificationRequired;
                                     }
                                 }
+                                3 => {
+                                    self.popup =
+                                        PopupMenu::History(data.local.user_levels.len() - i - 1, 0);
+                                }
+                                4 => {
+                                    data.duplicate_level(data.local.user_levels.len() - i - 1);
+                                    data.store();
+                                }
+                                #[cfg(not(target_arch = "wasm32"))]
+                                5 => {
+                                    let (name, level) = &data.local.user_levels
+                                        [data.local.user_levels.len() - i - 1];
+                                    let exported = LocalData::get_export_dir()
+                                        .and_then(|dir| LocalData::export_level(&dir, name, level));
+                                    self.popup = match exported {
+                                        Ok(_) => PopupMenu::Exported(format!(
+                                            "Saved to levels/{name}.ron"
+                                        )),
+                                        Err(e) => PopupMenu::Error(e),
+                                    };
+                                }
                                 _ => {
                                     panic!()
                                 }
@@ -258,14 +338,14 @@ impl<'a> MainMenu<'a> {
                     (scale_factor, BLACK),
                     (
                         (12.5 * scale_factor) as u16,
-                        &self.assets.font,
+                        self.assets.font(),
                         3.0 * scale_factor,
                     ),
                 );
                 let text_width = btn.draw();
                 if matches!(self.level_menu, LevelMenuType::BrowseOnline) {
                     draw_texture_ex(
-                        &self.assets.person_icon,
+                        self.assets.texture(ids::PERSON_ICON),
                         btn.pos.x + 2.0 * scale_factor,
                         btn.pos.y + size.y * scale_factor - 8.0 * scale_factor,
                         WHITE,
@@ -284,13 +364,13 @@ impl<'a> MainMenu<'a> {
                         TextParams {
                             color: LIGHTGRAY,
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
 
                     draw_texture_ex(
-                        &self.assets.time_icon,
+                        self.assets.texture(ids::TIME_ICON),
                         btn.pos.x - 33.0 * scale_factor + size.x * scale_factor,
                         btn.pos.y + 2.0 * scale_factor,
                         WHITE,
@@ -308,12 +388,12 @@ impl<'a> MainMenu<'a> {
                         TextParams {
                             color: LIGHTGRAY,
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
                     draw_texture_ex(
-                        &self.assets.download_icon,
+                        self.assets.texture(ids::DOWNLOAD_ICON),
                         btn.pos.x - 33.0 * scale_factor + size.x * scale_factor,
                         btn.pos.y + size.y * scale_factor - 9.0 * scale_factor,
                         WHITE,
@@ -331,7 +411,7 @@ impl<'a> MainMenu<'a> {
                         TextParams {
                             color: LIGHTGRAY,
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
@@ -343,7 +423,7 @@ impl<'a> MainMenu<'a> {
                 if matches!(self.level_menu, LevelMenuType::LocalLevels) {
                     if *data.verified_levels.get(name).unwrap_or(&false) {
                         draw_texture_ex(
-                            &self.assets.check,
+                            self.assets.texture(ids::CHECK),
                             btn.pos.x + 2.0 * scale_factor,
                             btn.pos.y + size.y * scale_factor - 12.0 * scale_factor,
                             WHITE,
@@ -359,7 +439,7 @@ impl<'a> MainMenu<'a> {
                     .contains(&data.online_levels[i].0)
                 {
                     draw_texture_ex(
-                        &self.assets.check,
+                        self.assets.texture(ids::CHECK),
                         btn.pos.x + 4.0 * scale_factor + text_width,
                         btn.pos.y + 2.0 * scale_factor,
                         WHITE,
@@ -370,11 +450,18 @@ impl<'a> MainMenu<'a> {
                     );
                 }
 
+                if !unallow_click && !outside_scroll_box && btn.is_hovered() {
+                    hovered = Some(i);
+                }
                 if !unallow_click && !outside_scroll_box && btn.is_hovered() && mouse_down {
                     match self.level_menu {
                         LevelMenuType::BrowseOnline => {
                             let name = data.online_levels[i].0.to_string();
                             if let Some(level) = data.cached_online_levels.get(&name) {
+                                if let Err(errors) = level.validate(&self.limits) {
+                                    self.popup = PopupMenu::Error(describe_errors(&errors));
+                                    break;
+                                }
                                 let (name, author) = name.split_once("-").unwrap();
                                 return MenuUpdateResult::PlayOnline(
                                     level.clone(),
@@ -388,15 +475,99 @@ impl<'a> MainMenu<'a> {
                             break;
                         }
                         LevelMenuType::LocalLevels => {
-                            return MenuUpdateResult::Create(Some(
-                                data.local.user_levels.len() - i - 1,
-                            ));
+                            let index = data.local.user_levels.len() - i - 1;
+                            if let Err(errors) =
+                                data.local.user_levels[index].1.validate(&self.limits)
+                            {
+                                self.popup = PopupMenu::Error(describe_errors(&errors));
+                                break;
+                            }
+                            return MenuUpdateResult::Create(Some(index));
                         }
                         _ => {}
                     }
                 }
             }
 
+            // preview of the hovered level, below the main menu buttons.
+            // online levels only have one once they've been downloaded. Previewing them before that
+            // needs the server to render and serve thumbnails (see [crate::thumbnail]),
+            // as downloading them here would count towards their downloads.
+            let hovered_level = hovered.map(|i| match self.level_menu {
+                LevelMenuType::BrowseOnline => {
+                    let name = &data.online_levels[i].0;
+                    let key = (true, name.clone(), 0);
+                    (key, data.cached_online_levels.get(name))
+                }
+                _ => {
+                    let (name, level) =
+                        &data.local.user_levels[data.local.user_levels.len() - i - 1];
+                    ((false, name.clone(), data.local_generation), Some(level))
+                }
+            });
+            if let Some((key, level)) = hovered_level {
+                let area_pos = vec2(22.0, 186.0) * scale_factor;
+                let area_size = vec2(156.0, 96.0);
+                UIRect::new(
+                    area_pos,
+                    area_size * scale_factor,
+                    MAKER_BG_COLOR,
+                    (scale_factor, BLACK),
+                )
+                .draw();
+                if let Some(level) = level
+                    && self.thumbnail.as_ref().is_none_or(|f| f.0 != key)
+                {
+                    if self.pending_thumbnail.as_ref().is_none_or(|f| f.0 != key) {
+                        let thumbnail = self.thumbnails.start(level, 150, 90);
+                        self.pending_thumbnail = Some((key.clone(), thumbnail));
+                    }
+                    let thumbnail = &mut self.pending_thumbnail.as_mut().unwrap().1;
+                    if let Some(image) =
+                        self.thumbnails
+                            .step(level, thumbnail, THUMBNAIL_TILES_PER_FRAME)
+                    {
+                        let texture = Texture2D::from_rgba8(
+                            image.width() as u16,
+                            image.height() as u16,
+                            &image,
+                        );
+                        texture.set_filter(FilterMode::Nearest);
+                        self.thumbnail = Some((key.clone(), texture));
+                        self.pending_thumbnail = None;
+                    }
+                }
+                if let Some((_, texture)) = self.thumbnail.as_ref().filter(|f| f.0 == key) {
+                    draw_texture_ex(
+                        texture,
+                        area_pos.x + (area_size.x - texture.width()) / 2.0 * scale_factor,
+                        area_pos.y + (area_size.y - texture.height()) / 2.0 * scale_factor,
+                        WHITE,
+                        DrawTextureParams {
+                            dest_size: Some(texture.size() * scale_factor),
+                            ..Default::default()
+                        },
+                    );
+                } else {
+                    let font_size = (12.0 * scale_factor) as u16;
+                    draw_text_ex(
+                        if level.is_some() {
+                            "Rendering preview"
+                        } else {
+                            "Download to preview"
+                        },
+                        area_pos.x + 24.0 * scale_factor,
+                        area_pos.y + 48.0 * scale_factor + font_size as f32 / 4.0,
+                        TextParams {
+                            color: LIGHTGRAY,
+                            font_size,
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                }
+            }
+
             // all the following is just to redraw the background over the areas
             // where scroll items would overflow into
             buttons_pos.y -= self.scroll * scale_factor;
@@ -418,8 +589,8 @@ impl<'a> MainMenu<'a> {
             if matches!(self.level_menu, LevelMenuType::LocalLevels) {
                 let btn = UIImageButton::new(
                     offset * scale_factor + buttons_pos,
-                    &self.assets.create_btn.frames[0].0,
-                    &self.assets.create_btn.frames[1].0,
+                    &self.assets.animation(ids::CREATE_BTN).frames[0].0,
+                    &self.assets.animation(ids::CREATE_BTN).frames[1].0,
                     scale_factor,
                     false,
                 );
@@ -427,11 +598,28 @@ impl<'a> MainMenu<'a> {
                 if btn.is_hovered() && mouse_down {
                     return MenuUpdateResult::Create(None);
                 }
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    let import_btn = UIImageButton::new(
+                        offset * scale_factor
+                            + buttons_pos
+                            + vec2(menu_size.x * scale_factor - 3.0 * scale_factor, 0.0)
+                            - vec2(26.0, 0.0) * scale_factor,
+                        &self.assets.animation(ids::IMPORT_BTN).frames[0].0,
+                        &self.assets.animation(ids::IMPORT_BTN).frames[1].0,
+                        scale_factor,
+                        false,
+                    );
+                    import_btn.draw();
+                    if import_btn.is_hovered() && mouse_down {
+                        self.popup = PopupMenu::Import(TextInputData::default());
+                    }
+                }
             } else {
                 let reload_btn = UIImageButton::new(
                     offset * scale_factor + buttons_pos,
-                    &self.assets.reload_btn.frames[0].0,
-                    &self.assets.reload_btn.frames[1].0,
+                    &self.assets.animation(ids::RELOAD_BTN).frames[0].0,
+                    &self.assets.animation(ids::RELOAD_BTN).frames[1].0,
                     scale_factor,
                     false,
                 );
@@ -442,9 +630,18 @@ impl<'a> MainMenu<'a> {
                 }
                 let sort_btn_offset = vec2(26.0, 0.0);
                 for (i, (anim, mode)) in [
-                    (&self.assets.sort_name_btn, LevelSorting::Name),
-                    (&self.assets.sort_downloads_btn, LevelSorting::Downloads),
-                    (&self.assets.sort_time_btn, LevelSorting::Time),
+                    (
+                        self.assets.animation(ids::SORT_NAME_BTN),
+                        LevelSorting::Name,
+                    ),
+                    (
+                        self.assets.animation(ids::SORT_DOWNLOADS_BTN),
+                        LevelSorting::Downloads,
+                    ),
+                    (
+                        self.assets.animation(ids::SORT_TIME_BTN),
+                        LevelSorting::Time,
+                    ),
                 ]
                 .into_iter()
                 .enumerate()
@@ -478,7 +675,7 @@ impl<'a> MainMenu<'a> {
                 buttons_pos.y + font_size as f32,
                 TextParams {
                     font_size,
-                    font: Some(&self.assets.font),
+                    font: Some(self.assets.font()),
                     ..Default::default()
                 },
             );
@@ -516,12 +713,12 @@ impl<'a> MainMenu<'a> {
         }
 
         draw_texture_ex(
-            &self.assets.logo,
+            self.assets.texture(ids::LOGO),
             22.0 * scale_factor,
             7.0 * scale_factor,
             WHITE,
             DrawTextureParams {
-                dest_size: Some(self.assets.logo.size() * scale_factor),
+                dest_size: Some(self.assets.texture(ids::LOGO).size() * scale_factor),
                 ..Default::default()
             },
         );
@@ -529,11 +726,15 @@ impl<'a> MainMenu<'a> {
         play_btn.draw();
         create_btn.draw();
 
-        let popup_size = if !matches!(self.popup, PopupMenu::Upload(_, _, _)) {
-            vec2(250.0, 105.0)
-        } else {
-            vec2(250.0, 150.0)
+        let popup_size = match self.popup {
+            PopupMenu::Upload(_, _, _) => vec2(250.0, 150.0),
+            PopupMenu::History(_, _) => vec2(250.0, 175.0),
+            _ => vec2(250.0, 105.0),
         };
+        if !matches!(self.popup, PopupMenu::History(_, _)) {
+            self.preview = None;
+        }
+        let mut history_action = None;
         if !matches!(self.popup, PopupMenu::None) {
             let pos =
                 (vec2(actual_screen_width, actual_screen_height) - popup_size * scale_factor) / 2.0;
@@ -561,7 +762,7 @@ impl<'a> MainMenu<'a> {
                         pos.y + font_size as f32,
                         TextParams {
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
@@ -573,7 +774,7 @@ impl<'a> MainMenu<'a> {
                         pos.y + font_size as f32,
                         TextParams {
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
@@ -585,13 +786,62 @@ impl<'a> MainMenu<'a> {
                         SKY_COLOR,
                         MAKER_BG_COLOR,
                         (scale_factor, BLACK),
-                        (font_size, &self.assets.font, 3.0 * scale_factor),
+                        (font_size, self.assets.font(), 3.0 * scale_factor),
                         data,
                         "Enter level name",
                         MAX_LEVEL_NAME_LENGTH,
                     );
                     input.draw();
                 }
+                PopupMenu::Import(data) => {
+                    draw_text_ex(
+                        "Import level",
+                        pos.x + 10.0 * scale_factor,
+                        pos.y + font_size as f32,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                    let size = vec2(225.0, 25.0);
+                    let font_size = (12.0 * scale_factor) as u16;
+                    let mut input = UITextInput::new(
+                        pos + vec2((popup_size.x - size.x) / 2.0, 36.0) * scale_factor,
+                        size * scale_factor,
+                        SKY_COLOR,
+                        MAKER_BG_COLOR,
+                        (scale_factor, BLACK),
+                        (font_size, self.assets.font(), 3.0 * scale_factor),
+                        data,
+                        "File name in levels folder",
+                        MAX_LEVEL_NAME_LENGTH,
+                    );
+                    input.draw();
+                }
+                PopupMenu::Exported(text) => {
+                    draw_text_ex(
+                        "Exported",
+                        pos.x + 10.0 * scale_factor,
+                        pos.y + font_size as f32,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                    let font_size = (12.0 * scale_factor) as u16;
+                    draw_text_ex(
+                        text,
+                        pos.x + (7.0) * scale_factor,
+                        pos.y + (font_size) as f32 + 30.0 * scale_factor,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                }
                 PopupMenu::Upload(_, name_input, author_input) => {
                     draw_text_ex(
                         "Upload level",
@@ -599,7 +849,7 @@ impl<'a> MainMenu<'a> {
                         pos.y + font_size as f32,
                         TextParams {
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
@@ -611,7 +861,7 @@ impl<'a> MainMenu<'a> {
                         SKY_COLOR,
                         MAKER_BG_COLOR,
                         (scale_factor, BLACK),
-                        (font_size, &self.assets.font, 3.0 * scale_factor),
+                        (font_size, self.assets.font(), 3.0 * scale_factor),
                         name_input,
                         "Enter level name",
                         MAX_LEVEL_NAME_LENGTH,
@@ -624,7 +874,7 @@ impl<'a> MainMenu<'a> {
                         SKY_COLOR,
                         MAKER_BG_COLOR,
                         (scale_factor, BLACK),
-                        (font_size, &self.assets.font, 3.0 * scale_factor),
+                        (font_size, self.assets.font(), 3.0 * scale_factor),
                         author_input,
                         "Enter author name",
                         MAX_AUTHOR_NAME_LENGTH,
@@ -638,12 +888,14 @@ impl<'a> MainMenu<'a> {
                         pos.y + font_size as f32,
                         TextParams {
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
                     draw_texture_ex(
-                        self.assets.spinner.get_at_time((self.time * 1000.0) as u32),
+                        self.assets
+                            .animation(ids::SPINNER)
+                            .get_at_time((self.time * 1000.0) as u32),
                         pos.x + 10.0 * scale_factor,
                         pos.y + font_size as f32 + 5.0 * scale_factor,
                         WHITE,
@@ -671,12 +923,14 @@ impl<'a> MainMenu<'a> {
                         pos.y + font_size as f32,
                         TextParams {
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
                     draw_texture_ex(
-                        self.assets.spinner.get_at_time((self.time * 1000.0) as u32),
+                        self.assets
+                            .animation(ids::SPINNER)
+                            .get_at_time((self.time * 1000.0) as u32),
                         pos.x + 10.0 * scale_factor,
                         pos.y + font_size as f32 + 5.0 * scale_factor,
                         WHITE,
@@ -689,6 +943,13 @@ impl<'a> MainMenu<'a> {
                         match result {
                             NetworkResult::Success => {
                                 self.popup = PopupMenu::None;
+                                if let Err(errors) =
+                                    data.cached_online_levels[&level].validate(&self.limits)
+                                {
+                                    data.cached_online_levels.remove(&level);
+                                    self.popup = PopupMenu::Error(describe_errors(&errors));
+                                    return MenuUpdateResult::None;
+                                }
                                 let (name, author) = level.split_once("-").unwrap();
                                 let level = data.cached_online_levels.get(&level).unwrap().clone();
                                 return MenuUpdateResult::PlayOnline(
@@ -708,18 +969,19 @@ impl<'a> MainMenu<'a> {
                         pos.y + font_size as f32,
                         TextParams {
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
                     let font_size = (12.0 * scale_factor) as u16;
-                    draw_text_ex(
+                    draw_multiline_text_ex(
                         text,
                         pos.x + (2.0) * scale_factor,
-                        pos.y + (font_size) as f32 + 40.0 * scale_factor,
+                        pos.y + (font_size) as f32 + 30.0 * scale_factor,
+                        None,
                         TextParams {
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
@@ -731,7 +993,7 @@ impl<'a> MainMenu<'a> {
                         pos.y + font_size as f32,
                         TextParams {
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
@@ -743,13 +1005,167 @@ impl<'a> MainMenu<'a> {
                         None,
                         TextParams {
                             font_size,
-                            font: Some(&self.assets.font),
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                }
+                PopupMenu::History(index, selected) => {
+                    draw_text_ex(
+                        "History",
+                        pos.x + 10.0 * scale_factor,
+                        pos.y + font_size as f32,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                    let snapshots = data.level_snapshots(&data.local.user_levels[*index].0);
+                    let font_size = (10.0 * scale_factor) as u16;
+                    if snapshots.is_empty() {
+                        draw_multiline_text_ex(
+                            "No saved versions yet. One is kept\neach time this level is saved.",
+                            pos.x + (7.0) * scale_factor,
+                            pos.y + (font_size) as f32 + 30.0 * scale_factor,
+                            None,
+                            TextParams {
+                                font_size,
+                                font: Some(self.assets.font()),
+                                ..Default::default()
+                            },
+                        );
+                    } else {
+                        let row_size = vec2(110.0, 14.0);
+                        for (i, (timestamp, _)) in snapshots.iter().rev().enumerate() {
+                            let btn = UITextButton::new(
+                                pos + vec2(7.0, 30.0 + i as f32 * (row_size.y + 2.0))
+                                    * scale_factor,
+                                row_size * scale_factor,
+                                format_timestamp(*timestamp),
+                                if *selected == i {
+                                    MAKER_BG_COLOR
+                                } else {
+                                    SKY_COLOR
+                                },
+                                MAKER_BG_COLOR,
+                                (scale_factor, BLACK),
+                                (font_size, self.assets.font(), 3.0 * scale_factor),
+                            );
+                            btn.draw();
+                            if btn.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
+                                *selected = i;
+                            }
+                        }
+                        *selected = (*selected).min(snapshots.len() - 1);
+                        let snapshot = &snapshots[snapshots.len() - 1 - *selected].1;
+                        if self
+                            .preview
+                            .as_ref()
+                            .is_none_or(|f| f.0 != (*index, *selected))
+                        {
+                            let renderer = LevelRenderer::new(snapshot, self.assets, SKY_COLOR);
+                            self.preview = Some(((*index, *selected), renderer));
+                        }
+                        // fit the preview into the area right of the list
+                        let area_pos = pos + vec2(125.0, 30.0) * scale_factor;
+                        let area_size = vec2(118.0, 78.0) * scale_factor;
+                        let renderer = &mut self.preview.as_mut().unwrap().1;
+                        let size = renderer.size
+                            * (area_size.x / renderer.size.x).min(area_size.y / renderer.size.y);
+                        let preview_pos = area_pos + (area_size - size) / 2.0;
+                        renderer.update_chunks(
+                            snapshot,
+                            Rect::new(0.0, 0.0, renderer.size.x, renderer.size.y),
+                            size.x / renderer.size.x,
+                        );
+                        set_default_camera();
+                        draw_rectangle(
+                            area_pos.x,
+                            area_pos.y,
+                            area_size.x,
+                            area_size.y,
+                            MAKER_BG_COLOR,
+                        );
+                        renderer.draw(
+                            Rect::new(0.0, 0.0, renderer.size.x, renderer.size.y),
+                            preview_pos,
+                            size.x / renderer.size.x,
+                        );
+                        draw_rectangle_lines(
+                            area_pos.x,
+                            area_pos.y,
+                            area_size.x,
+                            area_size.y,
+                            scale_factor,
+                            BLACK,
+                        );
+                        let button_size = vec2(57.0, 18.0);
+                        for (i, text) in ["Restore", "Fork"].into_iter().enumerate() {
+                            let btn = UITextButton::new(
+                                pos + vec2(125.0 + i as f32 * (button_size.x + 4.0), 112.0)
+                                    * scale_factor,
+                                button_size * scale_factor,
+                                text.to_string(),
+                                GREEN_COLOR,
+                                DARK_GREEN_COLOR,
+                                (scale_factor, BLACK),
+                                (font_size, self.assets.font(), 3.0 * scale_factor),
+                            );
+                            btn.draw();
+                            if btn.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
+                                history_action = Some((*index, snapshot.clone(), i == 1));
+                            }
+                        }
+                    }
+                }
+                PopupMenu::Recover => {
+                    draw_text_ex(
+                        "Restore level?",
+                        pos.x + 10.0 * scale_factor,
+                        pos.y + font_size as f32,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
+                            ..Default::default()
+                        },
+                    );
+                    let name = match &data.recovery {
+                        Some((Some(name), _)) => format!("'{name}'"),
+                        _ => "an unnamed level".to_string(),
+                    };
+                    let font_size = (10.0 * scale_factor) as u16;
+                    draw_multiline_text_ex(
+                        &format!(
+                            "The editor wasn't closed properly.\nRestore the unsaved changes to\n{name}?"
+                        ),
+                        pos.x + (7.0) * scale_factor,
+                        pos.y + (font_size) as f32 + 30.0 * scale_factor,
+                        None,
+                        TextParams {
+                            font_size,
+                            font: Some(self.assets.font()),
                             ..Default::default()
                         },
                     );
                 }
                 _ => {}
             }
+            if let Some((index, level, fork)) = history_action {
+                let name = data.local.user_levels[index].0.clone();
+                if fork {
+                    let name = data.unique_level_name(&name);
+                    data.local.user_levels.push((name, level));
+                } else {
+                    // keep the current version in the history, so restoring can be undone
+                    let current = data.local.user_levels[index].1.clone();
+                    data.snapshot_level(&name, &current);
+                    data.verified_levels.remove(&name);
+                    data.local.user_levels[index].1 = level;
+                }
+                data.store();
+                self.popup = PopupMenu::None;
+            }
             let button_size = vec2(90.0, 25.0);
             let button_offset = vec2(button_size.x + 5.0, 0.0);
 
@@ -766,7 +1182,7 @@ impl<'a> MainMenu<'a> {
                     (scale_factor, BLACK),
                     (
                         (12.0 * scale_factor) as u16,
-                        &self.assets.font,
+                        self.assets.font(),
                         3.0 * scale_factor,
                     ),
                 );
@@ -774,27 +1190,61 @@ impl<'a> MainMenu<'a> {
                 if yes.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
                     match &self.popup {
                         PopupMenu::Delete(index) => {
-                            data.local.user_levels.remove(*index);
-                            data.local.store();
+                            data.delete_level(*index);
+                            data.store();
                             self.popup = PopupMenu::None;
                         }
-                        PopupMenu::Rename(index, text_data) => {
-                            if data.rename_level(*index, text_data.text.clone()) {
-                                self.popup = PopupMenu::None;
-                                data.local.store();
-                            }
+                        PopupMenu::Rename(index, text_data)
+                            if data.rename_level(*index, text_data.text.clone()) =>
+                        {
+                            self.popup = PopupMenu::None;
+                            data.store();
                         }
-                        PopupMenu::Upload(index, name_data, author_data) => {
-                            if data.rename_level(*index, name_data.text.clone()) {
-                                data.local.store();
-                                data.upload_level(
-                                    data.local.user_levels[*index].clone().1,
-                                    name_data.text.to_string(),
-                                    author_data.text.to_string(),
-                                );
-                                self.popup = PopupMenu::Uploading;
+                        PopupMenu::Upload(index, _, _)
+                            if let Err(errors) =
+                                data.local.user_levels[*index].1.validate(&self.limits) =>
+                        {
+                            self.popup = PopupMenu::Error(describe_errors(&errors));
+                        }
+                        PopupMenu::Upload(index, name_data, author_data)
+                            if data.rename_level(*index, name_data.text.clone()) =>
+                        {
+                            data.store();
+                            let (name, level) = data.local.user_levels[*index].clone();
+                            data.upload_level(level, name, author_data.text.to_string());
+                            self.popup = PopupMenu::Uploading;
+                        }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        PopupMenu::Import(text_data) => {
+                            let name = text_data.text.trim().to_string();
+                            let imported = LocalData::get_export_dir()
+                                .and_then(|dir| LocalData::import_level(&dir, &name));
+                            match imported {
+                                Ok(level) => {
+                                    if let Err(errors) = level.validate(&self.limits) {
+                                        self.popup = PopupMenu::Error(describe_errors(&errors));
+                                    } else {
+                                        let name = data.unique_level_name(&name);
+                                        data.local.user_levels.push((name, level));
+                                        data.store();
+                                        self.popup = PopupMenu::None;
+                                    }
+                                }
+                                Err(e) => self.popup = PopupMenu::Error(e),
                             }
                         }
+                        PopupMenu::Recover
+                            if let Some((_, level)) = &data.recovery
+                                && let Err(errors) = level.validate(&self.limits) =>
+                        {
+                            // the autosave can't be opened, so don't offer it again
+                            self.popup = PopupMenu::Error(describe_errors(&errors));
+                            data.set_recovery(None);
+                        }
+                        PopupMenu::Recover => {
+                            self.popup = PopupMenu::None;
+                            return MenuUpdateResult::Recover;
+                        }
                         _ => {}
                     }
                 }
@@ -807,18 +1257,26 @@ impl<'a> MainMenu<'a> {
                     pos.y + (popup_size.y - button_size.y - 9.0) * scale_factor,
                 ),
                 button_size * scale_factor,
-                "Cancel".to_string(),
+                if matches!(self.popup, PopupMenu::Recover) {
+                    "Discard"
+                } else {
+                    "Cancel"
+                }
+                .to_string(),
                 SKY_COLOR,
                 MAKER_BG_COLOR,
                 (scale_factor, BLACK),
                 (
                     (12.0 * scale_factor) as u16,
-                    &self.assets.font,
+                    self.assets.font(),
                     3.0 * scale_factor,
                 ),
             );
             no.draw();
             if no.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
+                if let PopupMenu::Recover = self.popup {
+                    data.set_recovery(None);
+                }
                 self.popup = PopupMenu::None;
             }
         }
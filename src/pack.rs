@@ -0,0 +1,98 @@
+//! Mod for asset packs, which are a [Manifest] along with the contents of every file it lists.
+//!
+//! The files in `assets/` are embedded into the game as the default pack. Their list is generated
+//! from `assets/manifest.ron` by the build script.
+
+use std::{borrow::Cow, collections::HashMap};
+
+use nanoserde::DeRon;
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::manifest::MANIFEST_PATH;
+use crate::manifest::Manifest;
+
+const EMBEDDED_MANIFEST: &str = include_str!("../assets/manifest.ron");
+
+/// Every file of the default pack, by path
+const EMBEDDED_FILES: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/embedded_files.rs"));
+
+pub struct AssetPack {
+    pub manifest: Manifest,
+    /// Contents of the files of the pack, by path
+    files: HashMap<String, Cow<'static, [u8]>>,
+}
+impl AssetPack {
+    /// The pack built into the game
+    pub fn embedded() -> Self {
+        Self::new(
+            EMBEDDED_MANIFEST,
+            EMBEDDED_FILES
+                .iter()
+                .map(|(path, bytes)| (path.to_string(), Cow::Borrowed(*bytes)))
+                .collect(),
+        )
+        .unwrap_or_else(|e| panic!("embedded asset pack is invalid: {e}"))
+    }
+    /// Creates a pack, checking that it has every file it lists and every asset the game uses
+    pub fn new(manifest: &str, files: HashMap<String, Cow<'static, [u8]>>) -> Result<Self, String> {
+        let manifest: Manifest =
+            DeRon::deserialize_ron(manifest).map_err(|e| format!("manifest: {e}"))?;
+        let required: Manifest = DeRon::deserialize_ron(EMBEDDED_MANIFEST).unwrap();
+        manifest.check(&required, |path| files.contains_key(path))?;
+        Ok(Self { manifest, files })
+    }
+    /// Reads a pack from a directory on disk, with the manifest at its root
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn from_dir(dir: &std::path::Path) -> Result<Self, String> {
+        let manifest = std::fs::read_to_string(dir.join(MANIFEST_PATH))
+            .map_err(|e| format!("{MANIFEST_PATH}: {e}"))?;
+        let listed: Manifest =
+            DeRon::deserialize_ron(&manifest).map_err(|e| format!("{MANIFEST_PATH}: {e}"))?;
+        let mut files = HashMap::new();
+        for path in listed.files() {
+            // missing files are reported by the check, along with every other problem
+            if let Ok(bytes) = std::fs::read(dir.join(path)) {
+                files.insert(path.to_string(), Cow::Owned(bytes));
+            }
+        }
+        Self::new(&manifest, files)
+    }
+    /// Replaces the contents of a file of the pack, returning the old contents
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn set_file(&mut self, path: &str, bytes: Cow<'static, [u8]>) -> Cow<'static, [u8]> {
+        self.files
+            .insert(path.to_string(), bytes)
+            .unwrap_or_default()
+    }
+    pub fn file(&self, path: &str) -> &[u8] {
+        self.files
+            .get(path)
+            .unwrap_or_else(|| panic!("asset pack is missing {path}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_pack_is_valid() {
+        AssetPack::embedded();
+    }
+
+    #[test]
+    fn reports_missing_files_and_ids() {
+        let manifest = EMBEDDED_MANIFEST
+            .replacen("\"pix32.ttf\"", "\"missing.ttf\"", 1)
+            .replacen("id: \"logo\"", "id: \"renamed_logo\"", 1);
+        let files = EMBEDDED_FILES
+            .iter()
+            .map(|(path, bytes)| (path.to_string(), Cow::Borrowed(*bytes)))
+            .collect();
+        let Err(error) = AssetPack::new(&manifest, files) else {
+            panic!("pack should be invalid");
+        };
+        assert!(error.contains("missing.ttf is missing"), "{error}");
+        assert!(error.contains("texture logo is missing"), "{error}");
+    }
+}
@@ -144,7 +144,7 @@ impl Player {
 
         if self.died {
             draw_texture_ex(
-                &assets.player_die,
+                assets.player_die(),
                 self.pos.floor().x - 4.0,
                 self.pos.floor().y - 8.0,
                 WHITE,
@@ -156,7 +156,7 @@ impl Player {
         } else {
             draw_texture_ex(
                 assets
-                    .player_legs
+                    .player_legs()
                     .get_by_name(legs_animation)
                     .get_at_time((self.time * 1000.0) as u32),
                 self.pos.floor().x - 4.0,
@@ -169,7 +169,7 @@ impl Player {
             );
             draw_texture_ex(
                 assets
-                    .player_torso
+                    .player_torso()
                     .get_by_name(torso_animation)
                     .get_at_time((self.time * 1000.0) as u32),
                 self.pos.floor().x - 4.0,
@@ -1,14 +1,12 @@
 use crate::{
-    assets::{Animation, Assets},
+    assets::{Animation, Assets, ids},
     level::{Character, Level, LevelRenderer},
     player::{Player, PlayerUpdateResult, update_physicsbody},
     ui::*,
     utils::*,
 };
-use impl_new_derive::ImplNew;
 use macroquad::{miniquad::window::screen_size, prelude::*};
 
-#[derive(ImplNew)]
 struct AliveEnemy<'a> {
     pub pos: Vec2,
     pub animation: &'a Animation,
@@ -16,6 +14,12 @@ struct AliveEnemy<'a> {
     pub moving_left: bool,
     pub velocity: Vec2,
     pub death_frames: f32,
+    pub speed: f32,
+    /// X position the enemy started at, which patrolling is measured from
+    pub start_x: f32,
+    /// How far (in pixels) the enemy may walk from where it started, 0 for no limit
+    pub patrol_range: f32,
+    pub turn_at_ledges: bool,
 }
 #[derive(Clone, Copy)]
 enum RuntimeMenu {
@@ -64,14 +68,24 @@ impl<'a> GoblinRuntime<'a> {
                 .filter_map(|(pos, character, _)| match character {
                     Character::PlayerSpawn => None,
                     Character::Flag => None,
-                    Character::WanderEnemy(animation) => Some(AliveEnemy::new(
-                        vec2(pos.0, pos.1) + vec2(0.0, 8.0),
-                        &assets.enemies.animations[*animation],
-                        0.0,
-                        true,
-                        Vec2::ZERO,
-                        0.0,
-                    )),
+                    Character::WanderEnemy {
+                        animation,
+                        moving_left,
+                        speed,
+                        patrol_range,
+                        turn_at_ledges,
+                    } => Some(AliveEnemy {
+                        pos: vec2(pos.0, pos.1) + vec2(0.0, 8.0),
+                        animation: &assets.enemies().animations[*animation],
+                        time: 0.0,
+                        moving_left: *moving_left,
+                        velocity: Vec2::ZERO,
+                        death_frames: 0.0,
+                        speed: *speed,
+                        start_x: pos.0,
+                        patrol_range: *patrol_range as f32 * 16.0,
+                        turn_at_ledges: *turn_at_ledges,
+                    }),
                 })
                 .collect(),
             level_renderer: LevelRenderer::new(&level, assets, BLACK.with_alpha(0.0)),
@@ -94,22 +108,17 @@ impl<'a> GoblinRuntime<'a> {
 
         let result = self.player.update(delta_time, &self.level);
         self.pixel_camera.target = self.player.camera_pos.floor();
+        let view = Rect::new(
+            self.pixel_camera.target.x - SCREEN_WIDTH / 2.0,
+            self.pixel_camera.target.y - SCREEN_HEIGHT / 2.0,
+            SCREEN_WIDTH,
+            SCREEN_HEIGHT,
+        );
+        self.level_renderer.update_chunks(&self.level, view, 1.0);
         set_camera(&self.pixel_camera);
         clear_background(SKY_COLOR);
 
-        draw_texture_ex(
-            &self
-                .level_renderer
-                .camera
-                .render_target
-                .as_ref()
-                .unwrap()
-                .texture,
-            0.0,
-            0.0,
-            WHITE,
-            DrawTextureParams::default(),
-        );
+        self.level_renderer.draw(view, Vec2::ZERO, 1.0);
         self.enemies.retain_mut(|enemy| {
             if enemy.death_frames > 0.0 {
                 enemy.death_frames += delta_time;
@@ -127,18 +136,40 @@ impl<'a> GoblinRuntime<'a> {
             } else {
                 enemy.time += delta_time;
                 enemy.velocity.y += GRAVITY * delta_time;
-                enemy.velocity.x = if enemy.moving_left { -1.0 } else { 1.0 } * 32.0;
+                enemy.velocity.x = if enemy.moving_left { -1.0 } else { 1.0 } * enemy.speed;
                 let old = enemy.velocity;
-                enemy.pos = update_physicsbody(
+                let grounded;
+                (enemy.pos, grounded) = update_physicsbody(
                     enemy.pos,
                     &mut enemy.velocity,
                     delta_time,
                     &self.level,
                     true,
-                )
-                .0;
+                );
                 if old.x.abs() > enemy.velocity.x.abs() {
                     enemy.moving_left = !enemy.moving_left;
+                } else if enemy.patrol_range > 0.0
+                    && (enemy.pos.x - enemy.start_x).abs() >= enemy.patrol_range
+                    && (enemy.pos.x < enemy.start_x) == enemy.moving_left
+                {
+                    // walked as far as it may, head back
+                    enemy.moving_left = !enemy.moving_left;
+                } else if enemy.turn_at_ledges && grounded {
+                    // check the ground just ahead of the enemy's feet
+                    let ahead = if enemy.moving_left {
+                        enemy.pos.x - 1.0
+                    } else {
+                        enemy.pos.x + 8.0
+                    };
+                    let below = enemy.pos.y + 9.0;
+                    if ahead < 0.0
+                        || self
+                            .level
+                            .get_tile((ahead / 16.0) as usize, (below / 16.0) as usize)[0]
+                            == 0
+                    {
+                        enemy.moving_left = !enemy.moving_left;
+                    }
                 }
 
                 draw_texture_ex(
@@ -176,7 +207,7 @@ impl<'a> GoblinRuntime<'a> {
         }
         self.player.draw(self.assets);
 
-        self.assets.character_tileset.draw_tile(
+        self.assets.character_tileset().draw_tile(
             self.level.characters[1].0.0,
             self.level.characters[1].0.1,
             1.0,
@@ -224,7 +255,7 @@ impl<'a> GoblinRuntime<'a> {
                 pos.y + font_size as f32,
                 TextParams {
                     font_size,
-                    font: Some(&self.assets.font),
+                    font: Some(self.assets.font()),
                     ..Default::default()
                 },
             );
@@ -239,7 +270,7 @@ impl<'a> GoblinRuntime<'a> {
                     TextParams {
                         color: LIGHTGRAY,
                         font_size,
-                        font: Some(&self.assets.font),
+                        font: Some(self.assets.font()),
                         ..Default::default()
                     },
                 );
@@ -253,7 +284,7 @@ impl<'a> GoblinRuntime<'a> {
                     TextParams {
                         color: LIGHTGRAY,
                         font_size,
-                        font: Some(&self.assets.font),
+                        font: Some(self.assets.font()),
                         ..Default::default()
                     },
                 );
@@ -269,7 +300,7 @@ impl<'a> GoblinRuntime<'a> {
                     SKY_COLOR,
                     MAKER_BG_COLOR,
                     (scale_factor, BLACK),
-                    (font_size, &self.assets.font, 5.0 * scale_factor),
+                    (font_size, self.assets.font(), 5.0 * scale_factor),
                 );
                 if resume.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
                     self.menu = RuntimeMenu::None;
@@ -288,7 +319,7 @@ impl<'a> GoblinRuntime<'a> {
                 SKY_COLOR,
                 MAKER_BG_COLOR,
                 (scale_factor, BLACK),
-                (font_size, &self.assets.font, 5.0 * scale_factor),
+                (font_size, self.assets.font(), 5.0 * scale_factor),
             );
             return_to_menu.draw();
             if return_to_menu.is_hovered() && is_mouse_button_pressed(MouseButton::Left) {
@@ -300,8 +331,8 @@ impl<'a> GoblinRuntime<'a> {
         } else {
             let pause_btn = UIImageButton::new(
                 vec2(2.0, 2.0) * scale_factor,
-                &self.assets.pause_btn.frames[0].0,
-                &self.assets.pause_btn.frames[1].0,
+                &self.assets.animation(ids::PAUSE_BTN).frames[0].0,
+                &self.assets.animation(ids::PAUSE_BTN).frames[1].0,
                 scale_factor,
                 false,
             );
@@ -0,0 +1,371 @@
+//! Mod for rendering small previews of levels on the CPU.
+//!
+//! Doesn't touch the GPU or need a window, so it also works headless, and can encode previews as PNGs
+//! (see [ThumbnailRenderer::render_png]).
+//!
+//! Only levels the game has locally get a preview. Previewing online levels before they're downloaded
+//! would need the server to render and serve them, which the Python server can't do with this renderer,
+//! so that part isn't done.
+
+use std::{cell::RefCell, collections::HashMap, io::Cursor};
+
+use asefile::AsepriteFile;
+use image::{
+    ImageFormat, Pixel, Rgba, RgbaImage,
+    imageops::{self, FilterType},
+};
+
+use crate::{
+    autotile::AutotileRules,
+    level::{Character, Level},
+    pack::AssetPack,
+    utils::SKY_COLOR,
+};
+
+/// A preview that is being rendered, see [ThumbnailRenderer::start]
+pub struct Thumbnail {
+    image: RgbaImage,
+    /// Size in pixels that each tile is drawn with
+    tile_size: u32,
+    max_size: (u32, u32),
+    /// First row of tiles that isn't drawn yet
+    next_row: usize,
+}
+
+/// Which image a tile sprite comes from
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Sheet {
+    Terrain,
+    Obstacles,
+    Characters,
+    /// Index into the autotiles of the terrain
+    Autotile(usize),
+}
+
+pub struct ThumbnailRenderer {
+    terrain: RgbaImage,
+    obstacles: RgbaImage,
+    characters: RgbaImage,
+    autotiles: Vec<(AutotileRules, RgbaImage)>,
+    /// Sprites that were already scaled down, by sheet, tile position and size
+    scaled: RefCell<HashMap<(Sheet, u32, u32, u32), RgbaImage>>,
+}
+impl Default for ThumbnailRenderer {
+    fn default() -> Self {
+        Self::from_pack(&AssetPack::embedded())
+    }
+}
+impl ThumbnailRenderer {
+    /// Expects the files of the pack to be valid, which [Assets](crate::assets::Assets) makes sure of
+    pub fn from_pack(pack: &AssetPack) -> Self {
+        let manifest = &pack.manifest;
+        let tileset = |id: &str| {
+            manifest
+                .tilesets
+                .iter()
+                .find(|f| f.id == id)
+                .unwrap_or_else(|| panic!("no tileset with the id {id}"))
+        };
+        let load = |id: &str| {
+            let entry = tileset(id);
+            load_ase_image(pack.file(&entry.path), entry.layer)
+        };
+        Self {
+            terrain: load(&manifest.roles.terrain),
+            obstacles: load(&manifest.roles.obstacles),
+            characters: load(&manifest.roles.characters),
+            autotiles: tileset(&manifest.roles.terrain)
+                .autotiles
+                .iter()
+                .map(|path| AutotileRules::from_file(pack.file(path)).unwrap())
+                .collect(),
+            scaled: RefCell::new(HashMap::new()),
+        }
+    }
+    fn sheet(&self, sheet: Sheet) -> &RgbaImage {
+        match sheet {
+            Sheet::Terrain => &self.terrain,
+            Sheet::Obstacles => &self.obstacles,
+            Sheet::Characters => &self.characters,
+            Sheet::Autotile(index) => &self.autotiles[index].1,
+        }
+    }
+    /// Draws a 16x16 sprite from a sheet onto the image, scaled to `size` pixels
+    fn draw_sprite(
+        &self,
+        image: &mut RgbaImage,
+        sheet: Sheet,
+        tile: (u32, u32),
+        pos: (i64, i64),
+        size: u32,
+        flip: bool,
+    ) {
+        let mut scaled = self.scaled.borrow_mut();
+        let sprite = scaled
+            .entry((sheet, tile.0, tile.1, size))
+            .or_insert_with(|| {
+                let sprite =
+                    imageops::crop_imm(self.sheet(sheet), tile.0 * 16, tile.1 * 16, 16, 16)
+                        .to_image();
+                if size == 16 {
+                    sprite
+                } else {
+                    imageops::resize(&sprite, size, size, FilterType::Triangle)
+                }
+            });
+        if size == 1 {
+            // huge levels end up with a pixel per tile, which is much faster to blend directly
+            if let (Ok(x), Ok(y)) = (u32::try_from(pos.0), u32::try_from(pos.1))
+                && x < image.width()
+                && y < image.height()
+            {
+                image.get_pixel_mut(x, y).blend(sprite.get_pixel(0, 0));
+            }
+        } else if flip {
+            imageops::overlay(image, &imageops::flip_horizontal(sprite), pos.0, pos.1);
+        } else {
+            imageops::overlay(image, sprite, pos.0, pos.1);
+        }
+    }
+    /// Renders a preview of a level that fits within `max_width` x `max_height` pixels,
+    /// keeping its aspect ratio. Levels are never scaled up past their actual size.
+    pub fn render(&self, level: &Level, max_width: u32, max_height: u32) -> RgbaImage {
+        let mut thumbnail = self.start(level, max_width, max_height);
+        loop {
+            if let Some(image) = self.step(level, &mut thumbnail, usize::MAX) {
+                return image;
+            }
+        }
+    }
+    /// Starts rendering a preview a few rows at a time with [ThumbnailRenderer::step],
+    /// so previews of large levels can be spread over several frames.
+    pub fn start(&self, level: &Level, max_width: u32, max_height: u32) -> Thumbnail {
+        let (width, height) = (level.width as u32, level.height() as u32);
+        // draw each tile with a whole number of pixels, then scale the result down the rest of the way
+        let tile_size = (max_width / width).min(max_height / height).clamp(1, 16);
+        let sky =
+            Rgba([SKY_COLOR.r, SKY_COLOR.g, SKY_COLOR.b, SKY_COLOR.a].map(|f| (f * 255.0) as u8));
+        Thumbnail {
+            image: RgbaImage::from_pixel(width * tile_size, height * tile_size, sky),
+            tile_size,
+            max_size: (max_width, max_height),
+            next_row: 0,
+        }
+    }
+    /// Draws at least one more row of tiles of a preview, stopping once `max_tiles` were drawn.
+    /// After the last row, draws the characters and returns the finished preview.
+    ///
+    /// `level` has to be the level the preview was started with.
+    pub fn step(
+        &self,
+        level: &Level,
+        thumbnail: &mut Thumbnail,
+        max_tiles: usize,
+    ) -> Option<RgbaImage> {
+        let tile_size = thumbnail.tile_size;
+        let image = &mut thumbnail.image;
+        let rows = (max_tiles / level.width).max(1);
+        let end = (thumbnail.next_row + rows).min(level.height());
+        let tiles = &level.tiles[thumbnail.next_row * level.width..end * level.width];
+        for (index, tile_bundle) in tiles.iter().enumerate() {
+            let (x, y) = (
+                index % level.width,
+                thumbnail.next_row + index / level.width,
+            );
+            let pos = ((x as u32 * tile_size) as i64, (y as u32 * tile_size) as i64);
+            for (layer, id) in tile_bundle.iter().copied().enumerate() {
+                if id == 0 {
+                    continue;
+                }
+                if layer == 0
+                    && let Some(index) = self.autotiles.iter().position(|f| f.0.tile == id)
+                {
+                    let rules = &self.autotiles[index].0;
+                    if let Some(variant) = rules.variant(rules.neighbour_mask(level, 0, x, y)) {
+                        self.draw_sprite(
+                            image,
+                            Sheet::Autotile(index),
+                            (variant.x as u32, variant.y as u32),
+                            pos,
+                            tile_size,
+                            false,
+                        );
+                        continue;
+                    }
+                }
+                let tile = (id - 1) as u32;
+                self.draw_sprite(
+                    image,
+                    [Sheet::Terrain, Sheet::Obstacles][layer],
+                    (tile % 3, tile / 3),
+                    pos,
+                    tile_size,
+                    false,
+                );
+            }
+        }
+        thumbnail.next_row = end;
+        if end < level.height() {
+            return None;
+        }
+
+        let mut image = std::mem::take(image);
+        for (pos, character, index) in level.characters.iter() {
+            // enemy sprites face left
+            let flip = matches!(
+                character,
+                Character::WanderEnemy {
+                    moving_left: false,
+                    ..
+                }
+            );
+            let scale = tile_size as f32 / 16.0;
+            self.draw_sprite(
+                &mut image,
+                Sheet::Characters,
+                (*index as u32 % 3, *index as u32 / 3),
+                ((pos.0 * scale) as i64, (pos.1 * scale) as i64),
+                tile_size,
+                flip,
+            );
+        }
+
+        let (max_width, max_height) = thumbnail.max_size;
+        let scale = (max_width as f32 / image.width() as f32)
+            .min(max_height as f32 / image.height() as f32);
+        if scale < 1.0 {
+            image = imageops::resize(
+                &image,
+                ((image.width() as f32 * scale) as u32).max(1),
+                ((image.height() as f32 * scale) as u32).max(1),
+                FilterType::Triangle,
+            );
+        }
+        Some(image)
+    }
+    /// Same as [ThumbnailRenderer::render], but encoded as a PNG.
+    /// Only tests use this for now, as nothing serves previews yet.
+    #[cfg_attr(not(test), expect(dead_code))]
+    pub fn render_png(&self, level: &Level, max_width: u32, max_height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.render(level, max_width, max_height)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+}
+
+fn load_ase_image(bytes: &[u8], layer: Option<u32>) -> RgbaImage {
+    let ase = AsepriteFile::read(bytes).unwrap();
+    if let Some(layer) = layer {
+        ase.layer(layer).frame(0).image()
+    } else {
+        ase.frame(0).image()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::test_levels::{empty_level, test_level};
+
+    fn sky() -> Rgba<u8> {
+        Rgba([SKY_COLOR.r, SKY_COLOR.g, SKY_COLOR.b, SKY_COLOR.a].map(|f| (f * 255.0) as u8))
+    }
+
+    /// Checks that a tile of a thumbnail drawn at full size matches a sprite wherever it is opaque
+    fn assert_tile_matches(
+        image: &RgbaImage,
+        tile: (u32, u32),
+        sheet: &RgbaImage,
+        sprite: (u32, u32),
+    ) {
+        let mut opaque = 0;
+        for y in 0..16 {
+            for x in 0..16 {
+                let expected = sheet.get_pixel(sprite.0 * 16 + x, sprite.1 * 16 + y);
+                if expected[3] == 255 {
+                    assert_eq!(image.get_pixel(tile.0 * 16 + x, tile.1 * 16 + y), expected);
+                    opaque += 1;
+                }
+            }
+        }
+        assert!(opaque > 0);
+    }
+
+    #[test]
+    fn keeps_aspect_ratio() {
+        let renderer = ThumbnailRenderer::default();
+        for ((width, height), size) in [
+            ((8, 6), (120, 90)),
+            ((32, 8), (128, 32)),
+            ((100, 50), (100, 50)),
+            ((300, 100), (150, 50)),
+            ((4, 400), (1, 90)),
+        ] {
+            let image = renderer.render(&empty_level(width, height), 150, 90);
+            assert_eq!(image.dimensions(), size);
+        }
+    }
+
+    #[test]
+    fn never_scales_up() {
+        let renderer = ThumbnailRenderer::default();
+        let image = renderer.render(&empty_level(4, 4), 1000, 1000);
+        assert_eq!(image.dimensions(), (64, 64));
+    }
+
+    #[test]
+    fn draws_tiles() {
+        let renderer = ThumbnailRenderer::default();
+        let mut level = empty_level(6, 4);
+        // lone autotiled tile, and a tile that isn't autotiled
+        level.tiles[1 + 2 * 6] = [1, 0];
+        level.tiles[4 + 2 * 6] = [2, 0];
+        level.tiles[4 + 6] = [0, 1];
+        let image = renderer.render(&level, 96, 64);
+        assert_eq!(image.dimensions(), (96, 64));
+
+        assert_eq!(*image.get_pixel(40, 56), sky());
+        assert_tile_matches(&image, (4, 2), &renderer.terrain, (1, 0));
+        assert_tile_matches(&image, (4, 1), &renderer.obstacles, (0, 0));
+        let (rules, sheet) = &renderer.autotiles[0];
+        let variant = rules.variant(0).unwrap();
+        assert_tile_matches(&image, (1, 2), sheet, (variant.x as u32, variant.y as u32));
+    }
+
+    #[test]
+    fn draws_characters() {
+        let renderer = ThumbnailRenderer::default();
+        let mut level = empty_level(4, 4);
+        level.characters[1].0 = (32.0, 32.0);
+        let image = renderer.render(&level, 64, 64);
+        assert_tile_matches(&image, (0, 0), &renderer.characters, (0, 0));
+        assert_tile_matches(&image, (2, 2), &renderer.characters, (1, 0));
+    }
+
+    #[test]
+    fn encodes_png() {
+        let renderer = ThumbnailRenderer::default();
+        let png = renderer.render_png(&empty_level(8, 6), 150, 90);
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (120, 90));
+    }
+
+    #[test]
+    fn renders_over_several_steps() {
+        let renderer = ThumbnailRenderer::default();
+        let level = test_level();
+        let mut thumbnail = renderer.start(&level, 150, 90);
+        // a row at a time, even if it is wider than the limit
+        let mut steps = 1;
+        let image = loop {
+            if let Some(image) = renderer.step(&level, &mut thumbnail, 3) {
+                break image;
+            }
+            steps += 1;
+        };
+        assert_eq!(steps, level.height());
+        assert!(image == renderer.render(&level, 150, 90));
+    }
+}
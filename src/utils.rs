@@ -17,9 +17,36 @@ pub const ACCELERATION: f32 = 3600.0 / 2.0;
 pub const SCROLL_AMT: f32 = 1.1;
 pub const MIN_ZOOM: f32 = 0.001;
 
+/// Largest level size (in tiles) allowed in the editor
+pub const MAX_LEVEL_SIZE: usize = 1024;
+/// Width and height (in tiles) of the chunks levels are rendered in
+pub const LEVEL_CHUNK_SIZE: usize = 32;
+/// Memory (in bytes) the rendered chunks of a level may use before the ones out of view are freed
+pub const MAX_CHUNK_MEMORY: usize = 64 * 1024 * 1024;
+pub const MIN_LEVEL_SIZE: usize = 4;
+pub const MAX_ERASER_SIZE: usize = 8;
+/// Most tiles the fill tool may change at once, so filling the open sky by accident doesn't hang the editor
+pub const MAX_FILL_TILES: usize = 128 * 128;
+/// Most tiles of a level preview rendered each frame, so hovering a huge level doesn't stall the menu
+pub const THUMBNAIL_TILES_PER_FRAME: usize = 64 * 64;
+/// Seconds between autosaves of the level being edited
+pub const AUTOSAVE_INTERVAL: f32 = 30.0;
+/// How many saved versions of each local level are kept in its history
+pub const MAX_LEVEL_SNAPSHOTS: usize = 6;
+/// Most bytes the snapshots of all levels may take up in storage, as browsers only give each page a few megabytes
+pub const MAX_SNAPSHOTS_SIZE: usize = 512 * 1024;
+
+/// Walking speed of enemies (in pixels per second) unless set otherwise
+pub const DEFAULT_ENEMY_SPEED: f32 = 32.0;
+pub const MIN_ENEMY_SPEED: f32 = 8.0;
+pub const MAX_ENEMY_SPEED: f32 = 96.0;
+/// Furthest (in tiles) an enemy can be set to patrol from where it starts
+pub const MAX_PATROL_RANGE: u8 = 16;
+
 // remember to update server when modifying these values!
 pub const MAX_LEVEL_NAME_LENGTH: usize = 20;
 pub const MAX_AUTHOR_NAME_LENGTH: usize = 25;
+pub const MAX_STAMP_NAME_LENGTH: usize = 12;
 
 pub const SKY_COLOR: Color = Color::from_hex(0x29adff);
 pub const MAKER_BG_COLOR: Color = Color::from_hex(0x365987);
@@ -72,6 +99,26 @@ pub fn create_camera(w: f32, h: f32) -> Camera2D {
         ..Default::default()
     }
 }
+/// Formats a unix timestamp (in seconds) as a UTC date and time, like `2025-01-31 13:05`
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let minutes = timestamp % 86400 / 60;
+    // convert days since epoch to a civil date
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{year}-{month:02}-{day:02} {:02}:{:02}",
+        minutes / 60,
+        minutes % 60
+    )
+}
 pub fn get_input_axis() -> Vec2 {
     let mut i = Vec2::ZERO;
     if is_key_down(KeyCode::A) || is_key_down(KeyCode::Left) {